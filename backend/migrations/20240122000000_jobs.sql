@@ -0,0 +1,20 @@
+-- Durable job queue for background processing of raw materials.
+-- Workers claim rows with FOR UPDATE SKIP LOCKED and hold a lease (locked_until)
+-- while running; an expired lease means the worker died and the job can be reclaimed.
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind TEXT NOT NULL DEFAULT 'process_material',
+    raw_material_id UUID NOT NULL REFERENCES raw_materials(id),
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'running', 'done', 'dead'
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL DEFAULT 5,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(), -- Earliest time the job may be (re)tried
+    locked_until TIMESTAMPTZ, -- Lease expiry while status = 'running'
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT jobs_status_check CHECK (status IN ('pending', 'running', 'done', 'dead'))
+);
+
+CREATE INDEX IF NOT EXISTS jobs_claimable_idx ON jobs (status, run_at);
+CREATE INDEX IF NOT EXISTS jobs_raw_material_id_idx ON jobs (raw_material_id);
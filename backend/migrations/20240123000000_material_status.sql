@@ -0,0 +1,25 @@
+-- Processing lifecycle of a raw material, reported by GET /internal/ingest/{id}
+ALTER TABLE raw_materials
+    ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'queued', -- 'queued', 'extracting', 'embedding', 'done', 'failed'
+    ADD COLUMN IF NOT EXISTS error TEXT, -- Last error returned by process_material
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS processed_at TIMESTAMPTZ;
+
+UPDATE raw_materials SET status = 'done', processed_at = created_at WHERE processed = TRUE;
+
+ALTER TABLE raw_materials
+    ADD CONSTRAINT raw_materials_status_check
+    CHECK (status IN ('queued', 'extracting', 'embedding', 'done', 'failed'));
+
+CREATE INDEX IF NOT EXISTS raw_materials_status_idx ON raw_materials (status, created_at);
+
+-- Status transition history for each material
+CREATE TABLE IF NOT EXISTS material_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    raw_material_id UUID NOT NULL REFERENCES raw_materials(id),
+    status TEXT NOT NULL,
+    message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS material_events_raw_material_id_idx ON material_events (raw_material_id, created_at);
@@ -0,0 +1,11 @@
+-- Deduplication and revision tracking for ingested materials
+ALTER TABLE raw_materials
+    ADD COLUMN IF NOT EXISTS content_hash TEXT, -- SHA-256 of the cleaned, whitespace-normalized text
+    ADD COLUMN IF NOT EXISTS idempotency_key TEXT, -- Optional client-supplied Idempotency-Key header
+    ADD COLUMN IF NOT EXISTS revision INT NOT NULL DEFAULT 1,
+    ADD COLUMN IF NOT EXISTS previous_revision_id UUID REFERENCES raw_materials(id);
+
+-- Rows ingested before this migration keep a NULL hash and are never matched as duplicates
+CREATE UNIQUE INDEX IF NOT EXISTS raw_materials_content_hash_key ON raw_materials (content_hash);
+CREATE UNIQUE INDEX IF NOT EXISTS raw_materials_idempotency_key_key ON raw_materials (idempotency_key);
+CREATE INDEX IF NOT EXISTS raw_materials_url_revision_idx ON raw_materials (url, revision DESC);
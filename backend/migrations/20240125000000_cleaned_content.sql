@@ -0,0 +1,5 @@
+-- Normalized text extracted from raw_materials.content (HTML stripped of scripts,
+-- navigation and ads). Extraction prompts use this instead of the original.
+ALTER TABLE raw_materials
+    ADD COLUMN IF NOT EXISTS cleaned_content TEXT,
+    ADD COLUMN IF NOT EXISTS content_format TEXT NOT NULL DEFAULT 'text'; -- 'html', 'text'
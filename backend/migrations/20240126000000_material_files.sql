@@ -0,0 +1,13 @@
+-- Original files behind materials ingested through POST /internal/ingest/upload
+CREATE TABLE IF NOT EXISTS material_files (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    raw_material_id UUID NOT NULL REFERENCES raw_materials(id),
+    filename TEXT NOT NULL,
+    content_type TEXT,
+    size_bytes BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    sections JSONB NOT NULL DEFAULT '[]', -- [{label, offset, length}] into raw_materials.content
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS material_files_raw_material_id_idx ON material_files (raw_material_id);
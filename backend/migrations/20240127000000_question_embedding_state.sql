@@ -0,0 +1,26 @@
+-- Extraction results are saved in one transaction before any embedding is
+-- requested; each question then tracks its own embedding so a retried job
+-- only embeds what is missing
+ALTER TABLE raw_materials
+    ADD COLUMN IF NOT EXISTS questions_extracted_at TIMESTAMPTZ; -- Set once extracted questions are persisted
+
+ALTER TABLE questions
+    ADD COLUMN IF NOT EXISTS text_for_embedding TEXT,
+    ADD COLUMN IF NOT EXISTS embedding_status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'done'
+    ADD COLUMN IF NOT EXISTS embedding_error TEXT; -- Last embedding failure, cleared on success
+
+ALTER TABLE questions
+    ADD CONSTRAINT questions_embedding_status_check
+    CHECK (embedding_status IN ('pending', 'done'));
+
+UPDATE questions q SET embedding_status = 'done'
+WHERE EXISTS (SELECT 1 FROM embeddings e WHERE e.question_id = q.id);
+
+UPDATE raw_materials r SET questions_extracted_at = r.updated_at
+WHERE EXISTS (SELECT 1 FROM questions q WHERE q.raw_material_id = r.id);
+
+CREATE INDEX IF NOT EXISTS questions_pending_embedding_idx
+    ON questions (raw_material_id) WHERE embedding_status = 'pending';
+
+-- At most one embedding per question
+CREATE UNIQUE INDEX IF NOT EXISTS embeddings_question_id_key ON embeddings (question_id);
@@ -0,0 +1,17 @@
+-- One row per model API call, for cost accounting and monthly budgets
+CREATE TABLE IF NOT EXISTS llm_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    feature TEXT NOT NULL, -- 'extraction', 'exam_generation', 'explanation', 'verification', 'embedding'
+    model TEXT NOT NULL,
+    prompt_tokens INT NOT NULL DEFAULT 0,
+    completion_tokens INT NOT NULL DEFAULT 0,
+    embedding_tokens INT NOT NULL DEFAULT 0,
+    estimated_cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+    raw_material_id UUID REFERENCES raw_materials(id) ON DELETE SET NULL,
+    job_id UUID REFERENCES jobs(id) ON DELETE SET NULL,
+    exam_id UUID, -- Generated exams are not stored yet, so no foreign key
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS llm_usage_created_at_idx ON llm_usage (created_at);
+CREATE INDEX IF NOT EXISTS llm_usage_raw_material_id_idx ON llm_usage (raw_material_id);
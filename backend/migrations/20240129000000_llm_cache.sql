@@ -0,0 +1,13 @@
+-- Model responses keyed by a hash of provider, model, parameters and input,
+-- so identical calls are answered without going back to the provider
+CREATE TABLE IF NOT EXISTS llm_cache (
+    key TEXT PRIMARY KEY, -- SHA-256 hex digest
+    kind TEXT NOT NULL, -- 'generation' or 'embedding'
+    model TEXT NOT NULL,
+    response TEXT, -- Set for generations
+    embedding REAL[], -- Set for embeddings
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS llm_cache_expires_at_idx ON llm_cache (expires_at);
@@ -0,0 +1,5 @@
+-- Prompt template that produced each question (e.g. 'extraction@v2'), so
+-- quality can be compared across prompt versions
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS prompt_version TEXT;
+
+CREATE INDEX IF NOT EXISTS questions_prompt_version_idx ON questions (prompt_version);
@@ -0,0 +1,17 @@
+-- Typed CU-TEP items: `questions.content` holds a validated item whose type
+-- is also kept in its own column (NULL for questions saved before typing).
+-- Items that fail validation are kept aside for review instead.
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS question_type TEXT;
+
+CREATE INDEX IF NOT EXISTS questions_question_type_idx ON questions (question_type);
+
+CREATE TABLE IF NOT EXISTS quarantined_questions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    raw_material_id UUID REFERENCES raw_materials(id) ON DELETE CASCADE,
+    content JSONB NOT NULL, -- The item as extracted
+    errors TEXT[] NOT NULL, -- Why it does not fit its type
+    prompt_version TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS quarantined_questions_raw_material_id_idx ON quarantined_questions (raw_material_id);
@@ -0,0 +1,58 @@
+-- Passages are stored once and shared by the questions of their set (a
+-- reading passage, a listening transcript or a cloze text), instead of being
+-- copied into every question's content.
+CREATE TABLE IF NOT EXISTS passages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    raw_material_id UUID REFERENCES raw_materials(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('reading', 'transcript', 'cloze')),
+    label TEXT, -- How the source labels it, e.g. 'Passage 2'
+    content TEXT NOT NULL,
+    -- Readability; NULL for passages moved out of older questions
+    word_count INT,
+    sentence_count INT,
+    flesch_reading_ease DOUBLE PRECISION,
+    embedding vector(768),
+    embedding_status TEXT NOT NULL DEFAULT 'pending' CHECK (embedding_status IN ('pending', 'done')),
+    embedding_error TEXT, -- Last embedding failure, cleared on success
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS passages_raw_material_id_idx ON passages (raw_material_id);
+CREATE INDEX IF NOT EXISTS passages_embedding_idx ON passages USING hnsw (embedding vector_cosine_ops);
+
+-- Questions of a set point at their passage, in the order they appear
+ALTER TABLE questions
+    ADD COLUMN IF NOT EXISTS passage_id UUID REFERENCES passages(id),
+    ADD COLUMN IF NOT EXISTS passage_position INT;
+
+CREATE INDEX IF NOT EXISTS questions_passage_id_idx ON questions (passage_id, passage_position);
+
+ALTER TABLE quarantined_questions
+    ADD COLUMN IF NOT EXISTS passage_id UUID REFERENCES passages(id) ON DELETE SET NULL;
+
+-- Move the passages typed questions used to carry inline into the new table,
+-- one row per question; these keep their embedding status as 'pending' so the
+-- next run of their material embeds them.
+CREATE TEMPORARY TABLE moved_passages AS
+SELECT q.id AS question_id, gen_random_uuid() AS passage_id, q.raw_material_id,
+       CASE q.question_type
+           WHEN 'listening' THEN 'transcript'
+           WHEN 'sentence_completion' THEN 'cloze'
+           ELSE 'reading'
+       END AS kind,
+       q.content->>'passage_ref' AS label,
+       q.content->>'passage' AS content
+FROM questions q
+WHERE q.question_type IS NOT NULL AND COALESCE(q.content->>'passage', '') <> '';
+
+INSERT INTO passages (id, raw_material_id, kind, label, content)
+SELECT passage_id, raw_material_id, kind, label, content FROM moved_passages;
+
+UPDATE questions q SET passage_id = m.passage_id, passage_position = 0
+FROM moved_passages m
+WHERE q.id = m.question_id;
+
+UPDATE questions SET content = content - 'passage' - 'passage_ref'
+WHERE question_type IS NOT NULL AND (content ? 'passage' OR content ? 'passage_ref');
+
+DROP TABLE moved_passages;
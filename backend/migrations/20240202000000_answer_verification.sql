@@ -0,0 +1,25 @@
+-- Answer-key verification: each typed question is answered again by the
+-- model without its key, and the outcome is kept with a confidence score
+-- that retrieval and exam assembly can filter on. NULL until verified.
+ALTER TABLE questions
+    ADD COLUMN IF NOT EXISTS verification_status TEXT, -- 'verified', 'disagreement', 'ambiguous', 'missing_key'
+    ADD COLUMN IF NOT EXISTS answer_confidence DOUBLE PRECISION, -- 0 to 1
+    ADD COLUMN IF NOT EXISTS verification JSONB, -- Answers of each sample, the model's answer and prompt version
+    ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS verification_error TEXT; -- Last verification failure, cleared on success
+
+ALTER TABLE questions
+    ADD CONSTRAINT questions_verification_status_check
+    CHECK (verification_status IN ('verified', 'disagreement', 'ambiguous', 'missing_key'));
+
+CREATE INDEX IF NOT EXISTS questions_answer_confidence_idx ON questions (answer_confidence);
+
+-- Questions of a material still waiting for verification
+CREATE INDEX IF NOT EXISTS questions_pending_verification_idx
+    ON questions (raw_material_id) WHERE verification_status IS NULL AND question_type IS NOT NULL;
+
+-- Materials report the new pipeline stage
+ALTER TABLE raw_materials DROP CONSTRAINT IF EXISTS raw_materials_status_check;
+ALTER TABLE raw_materials
+    ADD CONSTRAINT raw_materials_status_check
+    CHECK (status IN ('queued', 'extracting', 'verifying', 'embedding', 'done', 'failed'));
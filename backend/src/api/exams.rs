@@ -0,0 +1,161 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::api::validation::require_one_of;
+use crate::core::education_manager::EducationManager;
+use crate::core::engines::{LlmExamEngine, RandomPersonalizationEngine};
+use crate::core::llm_error::LlmError;
+use crate::core::traits::ExamItem;
+use crate::core::usage::{self, Feature, UsageRecorder, UsageScope};
+use crate::core::verification::Verifier;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const DIFFICULTIES: &[&str] = &["easy", "medium", "hard"];
+
+#[derive(Deserialize)]
+pub struct StreamExamQuery {
+    pub user_id: Option<String>,
+    pub topic: Option<String>,
+    pub difficulty: Option<String>,
+}
+
+// Generates an exam and sends each passage and question as a Server-Sent
+// Event as soon as the model has written it. Events, in order:
+// `started` {exam_id, topic, difficulty, prompt_version}, then `passage`
+// {index, kind, label, text, readability} and `question`
+// {index, passage, ...question} events (a passage always comes before its
+// questions; with answer-key verification on, each question also carries
+// `verification_status` and `answer_confidence`), then `done` {exam_id, count, passages} or `error` {code, message}.
+pub async fn stream_exam_handler(
+    State(state): State<AppState>,
+    Query(query): Query<StreamExamQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let difficulty = query.difficulty.as_deref().unwrap_or("medium");
+    let user_id = query.user_id.as_deref().unwrap_or("").trim();
+    let topic = query
+        .topic
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+
+    let mut errors = Vec::new();
+    require_one_of(&mut errors, "difficulty", difficulty, DIFFICULTIES);
+    if user_id.is_empty() && topic.is_none() {
+        errors.push(FieldError::new("user_id", "user_id or topic is required"));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
+    }
+
+    if let Some(reason) = usage::budget_exceeded(&state.db, &state.config.usage).await? {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "budget_exceeded",
+            format!("Exam generation is paused: {}", reason),
+        ));
+    }
+
+    let exam_id = Uuid::new_v4();
+    let scope = UsageScope {
+        exam_id: Some(exam_id),
+        ..Default::default()
+    };
+    let recorder = UsageRecorder::new(state.db.clone(), &state.config.usage);
+    let llm = recorder.meter_llm(state.providers.llm.clone(), scope, Feature::ExamGeneration);
+    let mut engine = LlmExamEngine::new(
+        llm,
+        state.config.generation.exam.clone(),
+        state.config.prompts.clone(),
+    );
+    let verification = &state.config.verification;
+    if verification.samples > 0 {
+        let verifier = Verifier::new(
+            recorder.meter_llm(state.providers.llm.clone(), scope, Feature::Verification),
+            state.config.generation.verification.clone(),
+            &state.config.prompts,
+            verification.samples,
+        );
+        engine = engine.with_verifier(verifier, verification.min_confidence);
+    }
+    let manager = EducationManager::new(
+        Box::new(engine),
+        Box::new(RandomPersonalizationEngine),
+    );
+
+    let (topic, exam) = manager
+        .stream_personalized_exam(user_id, topic, difficulty)
+        .await
+        .map_err(|e| {
+            eprintln!("Exam {} failed to start: {}", exam_id, e);
+            ApiError::new(StatusCode::BAD_GATEWAY, "provider_error", e)
+        })?;
+
+    let started = event(
+        "started",
+        json!({
+            "exam_id": exam_id,
+            "topic": topic,
+            "difficulty": difficulty,
+            "prompt_version": exam.prompt_version,
+        }),
+    );
+
+    // The stream ends after `done` or the first `error`
+    let state = Some((exam.items, 0usize, 0usize));
+    let events = stream::unfold(state, move |state| async move {
+        let (mut items, count, passages) = state?;
+        match items.next().await {
+            Some(Ok(ExamItem::Passage(passage))) => Some((
+                event("passage", passage),
+                Some((items, count, passages + 1)),
+            )),
+            Some(Ok(ExamItem::Question(mut question))) => {
+                question["index"] = json!(count);
+                Some((
+                    event("question", question),
+                    Some((items, count + 1, passages)),
+                ))
+            }
+            Some(Err(e)) => {
+                eprintln!("Exam {} failed after {} questions: {}", exam_id, count, e);
+                let error = json!({ "code": error_code(&e), "message": e.to_string() });
+                Some((event("error", error), None))
+            }
+            None => {
+                let done = json!({ "exam_id": exam_id, "count": count, "passages": passages });
+                Some((event("done", done), None))
+            }
+        }
+    });
+
+    let events = stream::once(async { started })
+        .chain(events)
+        .map(Ok::<_, std::convert::Infallible>);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn event(name: &str, data: Value) -> Event {
+    Event::default().event(name).data(data.to_string())
+}
+
+// `code` of an `error` event
+fn error_code(e: &LlmError) -> &'static str {
+    match e {
+        LlmError::Network { .. } | LlmError::Unavailable { .. } | LlmError::RateLimited { .. } => {
+            "provider_unavailable"
+        }
+        LlmError::Blocked { .. } => "content_blocked",
+        LlmError::MalformedResponse { .. } => "malformed_response",
+        _ => "provider_error",
+    }
+}
@@ -1,60 +1,102 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::api::validation::{self, ValidJson, Validate};
+use crate::core::ingestion::{self, IngestOutcome, NewMaterial, SOURCE_TYPES};
+use crate::core::materials;
 use crate::AppState;
 use axum::{
-    extract::{Json, State},
-    http::StatusCode,
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use uuid::Uuid;
 
+// Missing fields deserialize as empty strings so they are reported by
+// `validate` as field errors rather than as a serde message
 #[derive(Deserialize)]
 pub struct IngestRequest {
+    #[serde(default)]
     pub url: String,
+    #[serde(default)]
     pub raw_content: String,
+    #[serde(default)]
     pub source_type: String,
 }
 
+impl Validate for IngestRequest {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        validation::require_http_url(&mut errors, "url", &self.url);
+        validation::require_non_empty(&mut errors, "raw_content", &self.raw_content);
+        validation::require_one_of(&mut errors, "source_type", &self.source_type, SOURCE_TYPES);
+        errors
+    }
+}
+
 pub async fn ingest_handler(
     State(state): State<AppState>,
-    Json(payload): Json<IngestRequest>,
-) -> impl IntoResponse {
-    // 1. Save Raw Material
-    let result = sqlx::query!(
-        "INSERT INTO raw_materials (url, content, source_type) VALUES ($1, $2, $3) RETURNING id",
-        payload.url,
-        payload.raw_content,
-        payload.source_type
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<IngestRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let material = NewMaterial {
+        url: payload.url,
+        content: payload.raw_content,
+        source_type: payload.source_type,
+        idempotency_key: headers
+            .get("Idempotency-Key")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
+    };
+
+    // 1. Save Raw Material and enqueue processing atomically, so a crash
+    // between the two can never leave a material without a job
+    let mut tx = state.db.begin().await?;
+    let outcome =
+        ingestion::ingest_material(&mut tx, &material, state.config.worker.max_attempts).await?;
+    tx.commit().await?;
+
+    Ok(match outcome {
+        IngestOutcome::Created {
+            id,
+            revision,
+            previous_revision_id,
+        } => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "id": id,
+                "status": "queued",
+                "revision": revision,
+                "previous_revision_id": previous_revision_id,
+            })),
+        ),
+        IngestOutcome::Duplicate { id } => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "id": id, "status": "duplicate" })),
+        ),
+        IngestOutcome::NoText => return Err(no_extractable_text()),
+    })
+}
+
+// `raw_content` that is only markup, scripts or page chrome
+fn no_extractable_text() -> ApiError {
+    ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "no_extractable_text",
+        "The content has no text once markup and scripts are removed",
     )
-    .fetch_one(&state.db)
-    .await;
-
-    match result {
-        Ok(record) => {
-            let state_clone = state.clone();
-            let gemini =
-                crate::core::gemini_client::GeminiClient::new(&crate::core::config::Config::init()); // Logic improvement needed for creating client from state
-
-            // Spawn background task
-            tokio::spawn(async move {
-                let _ = crate::core::processor::process_material(
-                    record.id,
-                    payload.raw_content,
-                    gemini,
-                    state_clone.db,
-                )
-                .await;
-            });
-
-            (
-                StatusCode::CREATED,
-                Json(serde_json::json!({ "id": record.id, "status": "queued" })),
-            )
-        }
-        Err(e) => {
-            eprintln!("Failed to ingest: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": "Database error" })),
-            )
-        }
-    }
+}
+
+pub async fn ingest_status_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let summary = materials::get_summary(&state.db, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Material not found"))?;
+    let history = materials::get_history(&state.db, id).await?;
+
+    let mut body = serde_json::to_value(&summary).unwrap_or_default();
+    body["history"] = serde_json::to_value(&history).unwrap_or_default();
+    Ok(Json(body))
 }
@@ -0,0 +1,196 @@
+use crate::api::error::ApiError;
+use crate::api::ingest::IngestRequest;
+use crate::api::validation::{self, Validate};
+use crate::core::ingestion::{self, IngestOutcome, NewMaterial};
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Json, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::IntoResponse,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_BATCH_ITEMS: usize = 1000;
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchItemResult {
+    Created {
+        index: usize,
+        id: Uuid,
+        revision: i32,
+        previous_revision_id: Option<Uuid>,
+    },
+    Duplicate {
+        index: usize,
+        id: Uuid,
+    },
+    Invalid {
+        index: usize,
+        reason: String,
+    },
+}
+
+// Accepts either a JSON array of IngestRequest objects or, with
+// `Content-Type: application/x-ndjson`, one IngestRequest per line. NDJSON
+// bodies are consumed as they stream in. Each accepted item is stored and
+// enqueued in its own transaction, so the per-URL lock it takes is released
+// before the next item; invalid items are reported and skipped.
+pub async fn ingest_batch_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse, ApiError> {
+    let is_ndjson = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/x-ndjson"))
+        .unwrap_or(false);
+
+    let mut batch = Batch {
+        db: state.db.clone(),
+        results: Vec::new(),
+        max_attempts: state.config.worker.max_attempts,
+    };
+
+    let limit = state.config.max_body_bytes;
+    if is_ndjson {
+        read_ndjson(&mut batch, body, limit).await?;
+    } else {
+        read_json_array(&mut batch, body, limit).await?;
+    }
+
+    let (mut created, mut duplicate, mut invalid) = (0, 0, 0);
+    for result in &batch.results {
+        match result {
+            BatchItemResult::Created { .. } => created += 1,
+            BatchItemResult::Duplicate { .. } => duplicate += 1,
+            BatchItemResult::Invalid { .. } => invalid += 1,
+        }
+    }
+
+    Ok(Json(serde_json::json!({
+        "summary": { "created": created, "duplicate": duplicate, "invalid": invalid },
+        "results": batch.results,
+    })))
+}
+
+struct Batch {
+    db: PgPool,
+    results: Vec<BatchItemResult>,
+    max_attempts: i32,
+}
+
+impl Batch {
+    async fn push(&mut self, item: Result<IngestRequest, String>) -> Result<(), ApiError> {
+        let index = self.results.len();
+        if index >= MAX_BATCH_ITEMS {
+            return Err(ApiError::payload_too_large(format!(
+                "Batch exceeds {} items",
+                MAX_BATCH_ITEMS
+            )));
+        }
+
+        let material = match item.and_then(|req| {
+            let errors = req.validate();
+            if errors.is_empty() {
+                Ok(to_new_material(req))
+            } else {
+                Err(validation::describe(&errors))
+            }
+        }) {
+            Ok(material) => material,
+            Err(reason) => {
+                self.results
+                    .push(BatchItemResult::Invalid { index, reason });
+                return Ok(());
+            }
+        };
+
+        let mut tx = self.db.begin().await?;
+        let outcome = ingestion::ingest_material(&mut tx, &material, self.max_attempts).await?;
+        tx.commit().await?;
+        let result = match outcome {
+            IngestOutcome::Created {
+                id,
+                revision,
+                previous_revision_id,
+            } => BatchItemResult::Created {
+                index,
+                id,
+                revision,
+                previous_revision_id,
+            },
+            IngestOutcome::Duplicate { id } => BatchItemResult::Duplicate { index, id },
+            IngestOutcome::NoText => BatchItemResult::Invalid {
+                index,
+                reason: "raw_content has no text once markup and scripts are removed".to_string(),
+            },
+        };
+        self.results.push(result);
+        Ok(())
+    }
+
+    async fn push_line(&mut self, line: &[u8]) -> Result<(), ApiError> {
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let item = serde_json::from_slice::<IngestRequest>(line).map_err(|e| e.to_string());
+        self.push(item).await
+    }
+}
+
+async fn read_json_array(batch: &mut Batch, body: Body, limit: usize) -> Result<(), ApiError> {
+    let bytes = axum::body::to_bytes(body, limit)
+        .await
+        .map_err(|_| body_too_large(limit))?;
+    let items: Vec<serde_json::Value> = serde_json::from_slice(&bytes)
+        .map_err(|e| ApiError::bad_request(format!("Expected a JSON array: {}", e)))?;
+
+    for value in items {
+        let item = serde_json::from_value::<IngestRequest>(value).map_err(|e| e.to_string());
+        batch.push(item).await?;
+    }
+    Ok(())
+}
+
+async fn read_ndjson(batch: &mut Batch, body: Body, limit: usize) -> Result<(), ApiError> {
+    let mut stream = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut total = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| ApiError::bad_request(e.to_string()))?;
+        total += chunk.len();
+        if total > limit {
+            return Err(body_too_large(limit));
+        }
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            batch.push_line(&line).await?;
+        }
+    }
+
+    // Last line without a trailing newline
+    batch.push_line(&buffer).await
+}
+
+fn body_too_large(limit: usize) -> ApiError {
+    ApiError::payload_too_large(format!("Request body exceeds {} bytes", limit))
+}
+
+fn to_new_material(req: IngestRequest) -> NewMaterial {
+    NewMaterial {
+        url: req.url,
+        content: req.raw_content,
+        source_type: req.source_type,
+        idempotency_key: None,
+    }
+}
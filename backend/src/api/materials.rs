@@ -0,0 +1,53 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::core::materials::{self, MaterialStatus};
+use crate::AppState;
+use axum::{
+    extract::{Json, Query, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct ListMaterialsQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub status: Option<String>,
+}
+
+pub async fn list_materials_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ListMaterialsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+
+    let status = match query.status.as_deref() {
+        None => None,
+        Some(s) => Some(MaterialStatus::parse(s).ok_or_else(|| {
+            ApiError::validation(vec![FieldError::new(
+                "status",
+                format!("unknown status: {}", s),
+            )])
+        })?),
+    };
+
+    // `page` comes straight from the query string, so the offset can overflow
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .ok_or_else(|| ApiError::validation(vec![FieldError::new("page", "is too large")]))?;
+
+    let (items, total) = materials::list_summaries(&state.db, status, per_page, offset).await?;
+
+    Ok(Json(serde_json::json!({
+        "items": items,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    })))
+}
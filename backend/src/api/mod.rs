@@ -1,2 +1,10 @@
+pub mod error;
+pub mod exams;
 pub mod ingest;
+pub mod ingest_batch;
+pub mod materials;
+pub mod questions;
+pub mod upload;
+pub mod usage;
+pub mod validation;
 // pub mod generate; // Coming soon
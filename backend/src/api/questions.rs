@@ -0,0 +1,91 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::api::validation::require_non_empty;
+use crate::core::accessors::PostgresVectorAccessor;
+use crate::core::traits::{EmbeddingTask, VectorAccessor};
+use crate::core::usage::{self, UsageRecorder, UsageScope};
+use crate::AppState;
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct SimilarQuestionsQuery {
+    pub text: Option<String>,
+    pub limit: Option<i64>,
+    pub min_confidence: Option<f64>,
+}
+
+// Stored questions closest to `text`. Questions whose answer key was verified
+// with less than `min_confidence` (default VERIFICATION_MIN_CONFIDENCE) are
+// left out; questions not verified yet are kept.
+pub async fn similar_questions_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SimilarQuestionsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let text = query.text.as_deref().unwrap_or("").trim();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let min_confidence = query
+        .min_confidence
+        .unwrap_or(state.config.verification.min_confidence);
+
+    let mut errors = Vec::new();
+    require_non_empty(&mut errors, "text", text);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        errors.push(FieldError::new(
+            "limit",
+            format!("must be between 1 and {}", MAX_LIMIT),
+        ));
+    }
+    if !(0.0..=1.0).contains(&min_confidence) {
+        errors.push(FieldError::new("min_confidence", "must be between 0 and 1"));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
+    }
+
+    if let Some(reason) = usage::budget_exceeded(&state.db, &state.config.usage).await? {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "budget_exceeded",
+            format!("Question search is paused: {}", reason),
+        ));
+    }
+
+    let recorder = UsageRecorder::new(state.db.clone(), &state.config.usage);
+    let embedder = recorder.meter_embedder(state.providers.embedder.clone(), UsageScope::default());
+    let embeddings = embedder
+        .embed_batch(
+            &[text.to_string()],
+            EmbeddingTask::RetrievalQuery,
+            state.config.embedding.dimensions,
+        )
+        .await
+        .map_err(|e| {
+            eprintln!("Question search failed to embed its query: {}", e);
+            ApiError::new(StatusCode::BAD_GATEWAY, "provider_error", e.to_string())
+        })?;
+    let vector = embeddings.vectors.into_iter().next().unwrap_or_default();
+
+    let questions = PostgresVectorAccessor::new(state.db.clone())
+        .find_similar_questions(&vector, limit, min_confidence)
+        .await
+        .map_err(|e| {
+            eprintln!("Question search failed: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+                "Database error",
+            )
+        })?;
+
+    Ok(Json(serde_json::json!({
+        "min_confidence": min_confidence,
+        "items": questions,
+    })))
+}
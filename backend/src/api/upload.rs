@@ -0,0 +1,172 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::api::validation;
+use crate::core::documents::{self, DocumentKind};
+use crate::core::ingestion::{self, IngestOutcome, NewMaterial};
+use crate::AppState;
+use axum::{
+    extract::{Json, Multipart, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+struct UploadedFile {
+    filename: String,
+    content_type: Option<String>,
+    bytes: Vec<u8>,
+}
+
+// Multipart upload of a PDF, DOCX or plain-text practice book.
+// Fields: `file` (required) and `url` (optional source reference; defaults to
+// `upload://<uuid>/<filename>`, so unrelated uploads that share a filename are
+// not taken for revisions of each other). The extracted text goes through the
+// same ingestion pipeline as IngestRequest and the original file is kept in
+// material_files.
+pub async fn upload_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut file: Option<UploadedFile> = None;
+    let mut url: Option<String> = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::from_status(e.status(), e.body_text()))?
+    {
+        match field.name() {
+            Some("file") => {
+                let filename = field.file_name().unwrap_or("upload").to_string();
+                let content_type = field.content_type().map(|s| s.to_string());
+                let bytes = field
+                    .bytes()
+                    .await
+                    .map_err(|e| ApiError::from_status(e.status(), e.body_text()))?;
+                file = Some(UploadedFile {
+                    filename,
+                    content_type,
+                    bytes: bytes.to_vec(),
+                });
+            }
+            Some("url") => {
+                let value = field
+                    .text()
+                    .await
+                    .map_err(|e| ApiError::from_status(e.status(), e.body_text()))?;
+                url = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+            }
+            _ => {}
+        }
+    }
+
+    let mut errors = Vec::new();
+    if let Some(url) = &url {
+        validation::require_http_url(&mut errors, "url", url);
+    }
+    let file = match file {
+        Some(file) if !file.bytes.is_empty() => file,
+        _ => {
+            errors.push(FieldError::new("file", "must be a non-empty file"));
+            return Err(ApiError::validation(errors));
+        }
+    };
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
+    }
+
+    // 1. Extract text locally (CPU-bound, so off the async runtime)
+    let kind = DocumentKind::detect(&file.filename, file.content_type.as_deref(), &file.bytes)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Only PDF, DOCX and plain-text files are supported",
+            )
+        })?;
+
+    let bytes = file.bytes;
+    let (document, bytes) = tokio::task::spawn_blocking(move || {
+        let document = documents::extract(kind, &bytes);
+        (document, bytes)
+    })
+    .await
+    .map_err(|e| {
+        // A panic in the parser is our fault, not the client's
+        eprintln!("Document extraction of {} panicked: {}", file.filename, e);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Document extraction failed",
+        )
+    })?;
+    let document = document
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "unreadable_document", e))?;
+
+    let (text, sections) = document.render();
+    if text.trim().is_empty() {
+        return Err(no_extractable_text());
+    }
+
+    let material = NewMaterial {
+        url: url.unwrap_or_else(|| format!("upload://{}/{}", Uuid::new_v4(), file.filename)),
+        content: text,
+        source_type: kind.source_type().to_string(),
+        idempotency_key: headers
+            .get("Idempotency-Key")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
+    };
+
+    // 2. Store material, original file and job together
+    let mut tx = state.db.begin().await?;
+    let outcome =
+        ingestion::ingest_material(&mut tx, &material, state.config.worker.max_attempts).await?;
+
+    if let IngestOutcome::Created { id, .. } = &outcome {
+        sqlx::query!(
+            "INSERT INTO material_files (raw_material_id, filename, content_type, size_bytes, data, sections) VALUES ($1, $2, $3, $4, $5, $6)",
+            id,
+            file.filename,
+            file.content_type,
+            bytes.len() as i64,
+            bytes,
+            serde_json::to_value(&sections).unwrap_or_default()
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(match outcome {
+        IngestOutcome::Created {
+            id,
+            revision,
+            previous_revision_id,
+        } => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "id": id,
+                "status": "queued",
+                "source_type": kind.source_type(),
+                "sections": sections.len(),
+                "revision": revision,
+                "previous_revision_id": previous_revision_id,
+            })),
+        ),
+        IngestOutcome::Duplicate { id } => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "id": id, "status": "duplicate" })),
+        ),
+        IngestOutcome::NoText => return Err(no_extractable_text()),
+    })
+}
+
+fn no_extractable_text() -> ApiError {
+    ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "no_extractable_text",
+        "The file contains no extractable text (scanned documents are not supported)",
+    )
+}
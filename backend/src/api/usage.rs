@@ -0,0 +1,68 @@
+use crate::api::error::{ApiError, FieldError};
+use crate::core::usage::{self, UsageGrouping};
+use crate::AppState;
+use axum::{
+    extract::{Json, Query, State},
+    response::IntoResponse,
+};
+use chrono::{Days, NaiveDate, Utc};
+use serde::Deserialize;
+
+const DEFAULT_DAYS: u64 = 30;
+const MAX_DAYS: i64 = 366;
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    pub group_by: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+// Token usage and estimated cost grouped by day, source type or feature,
+// plus where this month stands against the configured budgets
+pub async fn usage_handler(
+    State(state): State<AppState>,
+    Query(query): Query<UsageQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let group_by = query.group_by.as_deref().unwrap_or("day");
+    let grouping = UsageGrouping::parse(group_by).ok_or_else(|| {
+        ApiError::validation(vec![FieldError::new(
+            "group_by",
+            format!("must be one of day, source, feature (got {})", group_by),
+        )])
+    })?;
+
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query
+        .from
+        .unwrap_or_else(|| to - Days::new(DEFAULT_DAYS - 1));
+    if from > to {
+        return Err(ApiError::validation(vec![FieldError::new(
+            "from",
+            "must not be after `to`",
+        )]));
+    }
+    if (to - from).num_days() >= MAX_DAYS {
+        return Err(ApiError::validation(vec![FieldError::new(
+            "from",
+            format!("range must not exceed {} days", MAX_DAYS),
+        )]));
+    }
+
+    let items = usage::summarize(&state.db, grouping, from, to).await?;
+    let month = usage::month_to_date(&state.db).await?;
+    let exceeded = usage::budget_exceeded(&state.db, &state.config.usage).await?;
+
+    Ok(Json(serde_json::json!({
+        "group_by": group_by,
+        "from": from,
+        "to": to,
+        "items": items,
+        "month_to_date": month,
+        "budget": {
+            "monthly_budget_usd": state.config.usage.monthly_budget_usd,
+            "monthly_token_budget": state.config.usage.monthly_token_budget,
+            "exceeded": exceeded,
+        },
+    })))
+}
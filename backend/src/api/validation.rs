@@ -0,0 +1,116 @@
+use crate::api::error::{ApiError, FieldError};
+use async_trait::async_trait;
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    Json,
+};
+use serde::de::DeserializeOwned;
+
+// Request payloads that check their own content after deserialization
+pub trait Validate {
+    // Returns one entry per invalid field; empty when the payload is valid
+    fn validate(&self) -> Vec<FieldError>;
+}
+
+// Like `Json<T>`, but rejects with an `ApiError` and runs `Validate`
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(json_rejection)?;
+
+        let errors = value.validate();
+        if !errors.is_empty() {
+            return Err(ApiError::validation(errors));
+        }
+        Ok(ValidJson(value))
+    }
+}
+
+fn json_rejection(rejection: JsonRejection) -> ApiError {
+    let status = rejection.status();
+    let message = rejection.body_text();
+    match rejection {
+        JsonRejection::JsonDataError(_) => ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+            message,
+        ),
+        JsonRejection::JsonSyntaxError(_) => {
+            ApiError::new(StatusCode::BAD_REQUEST, "malformed_json", message)
+        }
+        _ => ApiError::from_status(status, message),
+    }
+}
+
+// Shared field checks
+
+pub fn require_non_empty(errors: &mut Vec<FieldError>, field: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    }
+}
+
+pub fn require_http_url(errors: &mut Vec<FieldError>, field: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+        return;
+    }
+    match reqwest::Url::parse(value) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(_) => errors.push(FieldError::new(field, "must be an http or https URL")),
+        Err(e) => errors.push(FieldError::new(field, format!("is not a valid URL: {}", e))),
+    }
+}
+
+pub fn require_one_of(errors: &mut Vec<FieldError>, field: &str, value: &str, allowed: &[&str]) {
+    if !allowed.contains(&value) {
+        errors.push(FieldError::new(
+            field,
+            format!("must be one of: {}", allowed.join(", ")),
+        ));
+    }
+}
+
+// Flattens field errors into a single line, for per-item batch results
+pub fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{} {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_checks() {
+        let mut errors = Vec::new();
+        require_http_url(&mut errors, "url", "https://example.com/a");
+        require_non_empty(&mut errors, "raw_content", "text");
+        require_one_of(&mut errors, "source_type", "web", &["web", "pdf"]);
+        assert!(errors.is_empty());
+
+        require_http_url(&mut errors, "url", "ftp://example.com");
+        require_http_url(&mut errors, "url", "not a url");
+        require_non_empty(&mut errors, "raw_content", "  \n");
+        require_one_of(&mut errors, "source_type", "fax", &["web", "pdf"]);
+        assert_eq!(errors.len(), 4);
+        assert_eq!(
+            describe(&errors[3..]),
+            "source_type must be one of: web, pdf"
+        );
+    }
+}
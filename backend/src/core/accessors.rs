@@ -17,15 +17,111 @@ impl PostgresVectorAccessor {
 impl VectorAccessor for PostgresVectorAccessor {
     async fn find_similar_questions(
         &self,
-        _vector: &[f32],
-        _limit: i64,
+        vector: &[f32],
+        limit: i64,
+        min_confidence: f64,
     ) -> Result<Vec<Value>, String> {
-        // Placeholder implementation for MVP
-        // In real VBD, this hides the complexity of pgvector queries
+        let embedding = pgvector::Vector::from(vector.to_vec());
+        sqlx::query_scalar!(
+            r#"
+            SELECT q.content || jsonb_build_object(
+                'id', q.id,
+                'verification_status', q.verification_status,
+                'answer_confidence', q.answer_confidence
+            ) AS "question!"
+            FROM embeddings e
+            JOIN questions q ON q.id = e.question_id
+            WHERE q.answer_confidence IS NULL OR q.answer_confidence >= $3
+            ORDER BY e.embedding <=> $1
+            LIMIT $2
+            "#,
+            embedding as pgvector::Vector,
+            limit,
+            min_confidence
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_similar_questions_skip_only_low_confidence_keys() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let raw_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO raw_materials (id, url, content, source_type) VALUES ($1, $2, $3, $4)",
+            raw_id,
+            "http://test.com/similar-questions",
+            "Similar Questions Test Content",
+            "unit-test"
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert raw material");
+
+        // A direction no other test embeds along, so these are the nearest
+        let mut vector = vec![0.0; 768];
+        vector[767] = 1.0;
+        let mut ids = Vec::new();
+        for confidence in [None, Some(0.9), Some(0.2)] {
+            let id = Uuid::new_v4();
+            sqlx::query!(
+                "INSERT INTO questions (id, raw_material_id, topic, content, answer_confidence) VALUES ($1, $2, 'reading', '{}', $3)",
+                id,
+                raw_id,
+                confidence
+            )
+            .execute(&pool)
+            .await
+            .expect("Failed to insert question");
+            sqlx::query!(
+                "INSERT INTO embeddings (question_id, chunk_text, embedding) VALUES ($1, 'Similar question', $2)",
+                id,
+                pgvector::Vector::from(vector.clone()) as pgvector::Vector
+            )
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embedding");
+            ids.push(id);
+        }
 
-        // Example query (commented out as schema might vary):
-        // sqlx::query!("SELECT content FROM questions ORDER BY embedding <-> $1 LIMIT $2", vector, limit)
+        let accessor = PostgresVectorAccessor::new(pool.clone());
+        let found = accessor
+            .find_similar_questions(&vector, 2, 0.5)
+            .await
+            .expect("Failed to find similar questions");
+        let mut found: Vec<String> = found
+            .iter()
+            .map(|q| q["id"].as_str().unwrap_or_default().to_string())
+            .collect();
+        found.sort();
+        let mut expected = vec![ids[0].to_string(), ids[1].to_string()];
+        expected.sort();
+        // The unverified question is kept, the one below 0.5 is not
+        assert_eq!(found, expected);
 
-        Ok(vec![])
+        sqlx::query!("DELETE FROM embeddings WHERE question_id = ANY($1)", &ids)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM questions WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .unwrap();
     }
 }
@@ -0,0 +1,475 @@
+use crate::core::config::{CacheConfig, GenerationParams};
+use crate::core::llm_error::LlmError;
+use crate::core::streaming;
+use crate::core::traits::{
+    EmbeddingProvider, EmbeddingTask, Embeddings, Generation, GenerationStream, LlmProvider,
+    TokenUsage,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+// Content-addressed store for model responses. A response is keyed by a hash
+// of the provider, model, every parameter that affects the output, and the
+// input, so changing any of them misses the cache.
+#[derive(Clone)]
+pub struct ResponseCache {
+    pool: PgPool,
+    ttl_secs: f64,
+}
+
+impl ResponseCache {
+    // `None` when the cache is disabled
+    pub fn new(pool: PgPool, config: &CacheConfig) -> Option<Self> {
+        (config.ttl_secs > 0).then(|| Self {
+            pool,
+            ttl_secs: config.ttl_secs as f64,
+        })
+    }
+
+    // A generation provider that answers repeated prompts from the cache.
+    // `provider` identifies the backend and `default_model` the model used
+    // when `GenerationParams::model` is unset.
+    pub fn wrap_llm(
+        &self,
+        inner: Arc<dyn LlmProvider>,
+        provider: String,
+        default_model: String,
+    ) -> Arc<dyn LlmProvider> {
+        Arc::new(CachedLlm {
+            inner,
+            cache: self.clone(),
+            provider,
+            default_model,
+        })
+    }
+
+    // An embedding provider that only sends texts it has not embedded before
+    pub fn wrap_embedder(
+        &self,
+        inner: Arc<dyn EmbeddingProvider>,
+        provider: String,
+        model: String,
+    ) -> Arc<dyn EmbeddingProvider> {
+        Arc::new(CachedEmbedder {
+            inner,
+            cache: self.clone(),
+            provider,
+            model,
+        })
+    }
+
+    // Lookups and writes never fail the call they serve; errors are logged
+    // and treated as a miss
+    async fn get_generation(&self, key: &str) -> Option<(String, String)> {
+        let result = sqlx::query!(
+            r#"SELECT model, response AS "response!" FROM llm_cache WHERE key = $1 AND kind = 'generation' AND response IS NOT NULL AND expires_at > NOW()"#,
+            key
+        )
+        .fetch_optional(&self.pool)
+        .await;
+        match result {
+            Ok(row) => row.map(|r| (r.model, r.response)),
+            Err(e) => {
+                eprintln!("Failed to read LLM cache: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn put_generation(&self, key: &str, generation: &Generation) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO llm_cache (key, kind, model, response, expires_at)
+            VALUES ($1, 'generation', $2, $3, NOW() + make_interval(secs => $4))
+            ON CONFLICT (key) DO UPDATE
+            SET model = EXCLUDED.model, response = EXCLUDED.response, created_at = NOW(), expires_at = EXCLUDED.expires_at
+            "#,
+            key,
+            generation.model,
+            generation.text,
+            self.ttl_secs
+        )
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            eprintln!("Failed to write LLM cache: {}", e);
+        }
+    }
+
+    async fn get_embeddings(&self, keys: &[String]) -> HashMap<String, Vec<f32>> {
+        let result = sqlx::query!(
+            r#"SELECT key, embedding AS "embedding!" FROM llm_cache WHERE key = ANY($1) AND kind = 'embedding' AND embedding IS NOT NULL AND expires_at > NOW()"#,
+            keys
+        )
+        .fetch_all(&self.pool)
+        .await;
+        match result {
+            Ok(rows) => rows.into_iter().map(|r| (r.key, r.embedding)).collect(),
+            Err(e) => {
+                eprintln!("Failed to read embedding cache: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    async fn put_embedding(&self, key: &str, model: &str, vector: &[f32]) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO llm_cache (key, kind, model, embedding, expires_at)
+            VALUES ($1, 'embedding', $2, $3, NOW() + make_interval(secs => $4))
+            ON CONFLICT (key) DO UPDATE
+            SET model = EXCLUDED.model, embedding = EXCLUDED.embedding, created_at = NOW(), expires_at = EXCLUDED.expires_at
+            "#,
+            key,
+            model,
+            vector,
+            self.ttl_secs
+        )
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            eprintln!("Failed to write embedding cache: {}", e);
+        }
+    }
+}
+
+// Drop entries past their TTL; expired entries are already ignored by
+// lookups, this only reclaims the space
+pub async fn purge_expired(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM llm_cache WHERE expires_at <= NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// Everything that determines a generation's output. Also names recorded
+// fixtures (see core::fixtures).
+pub fn generation_request(
+    method: &str,
+    provider: &str,
+    default_model: &str,
+    prompt: &str,
+    schema: Option<&Value>,
+    params: &GenerationParams,
+) -> Value {
+    json!({
+        "method": method,
+        "provider": provider,
+        "model": params.model.as_deref().unwrap_or(default_model),
+        "temperature": params.temperature,
+        "top_p": params.top_p,
+        "max_output_tokens": params.max_output_tokens,
+        "stop_sequences": params.stop_sequences,
+        "system_instruction": params.system_instruction,
+        "safety_threshold": params.safety_threshold,
+        "schema": schema,
+        "prompt": prompt,
+    })
+}
+
+// serde_json objects keep their keys sorted, so equal requests always
+// serialize, and hash, the same way
+pub fn request_hash(request: &Value) -> String {
+    format!("{:x}", Sha256::digest(request.to_string().as_bytes()))
+}
+
+struct CachedLlm {
+    inner: Arc<dyn LlmProvider>,
+    cache: ResponseCache,
+    provider: String,
+    default_model: String,
+}
+
+impl CachedLlm {
+    fn key(
+        &self,
+        method: &str,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> String {
+        request_hash(&generation_request(
+            method,
+            &self.provider,
+            &self.default_model,
+            prompt,
+            schema,
+            params,
+        ))
+    }
+
+    // A hit costs nothing, so it is returned with zero usage
+    async fn cached(
+        &self,
+        key: String,
+        params: &GenerationParams,
+        call: impl Future<Output = Result<Generation, LlmError>> + Send,
+    ) -> Result<Generation, LlmError> {
+        if !params.bypass_cache {
+            if let Some((model, text)) = self.cache.get_generation(&key).await {
+                return Ok(Generation {
+                    text,
+                    model,
+                    usage: TokenUsage::default(),
+                });
+            }
+        }
+        let generation = call.await?;
+        self.cache.put_generation(&key, &generation).await;
+        Ok(generation)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachedLlm {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let key = self.key("generate_text", prompt, None, params);
+        self.cached(key, params, self.inner.generate_text(prompt, params))
+            .await
+    }
+
+    async fn generate_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let key = self.key("generate_json", prompt, schema, params);
+        self.cached(
+            key,
+            params,
+            self.inner.generate_json(prompt, schema, params),
+        )
+        .await
+    }
+
+    // Shares entries with `generate_json`: a hit is replayed as one chunk and
+    // a streamed reply is stored once it is complete
+    async fn stream_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<GenerationStream, LlmError> {
+        let key = self.key("generate_json", prompt, schema, params);
+        if !params.bypass_cache {
+            if let Some((model, text)) = self.cache.get_generation(&key).await {
+                return Ok(GenerationStream {
+                    model,
+                    chunks: streaming::single_chunk(text, Some(TokenUsage::default())),
+                });
+            }
+        }
+        let stream = self.inner.stream_json(prompt, schema, params).await?;
+        let (cache, model) = (self.cache.clone(), stream.model.clone());
+        let chunks = streaming::on_complete(stream.chunks, move |text, usage| async move {
+            let generation = Generation {
+                text,
+                model,
+                usage: usage.unwrap_or_default(),
+            };
+            cache.put_generation(&key, &generation).await;
+        });
+        Ok(GenerationStream {
+            model: stream.model,
+            chunks,
+        })
+    }
+}
+
+struct CachedEmbedder {
+    inner: Arc<dyn EmbeddingProvider>,
+    cache: ResponseCache,
+    provider: String,
+    model: String,
+}
+
+#[async_trait]
+impl EmbeddingProvider for CachedEmbedder {
+    // Cached vectors are reused and only the misses are sent, so usage
+    // covers the misses alone
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError> {
+        let keys: Vec<String> = texts
+            .iter()
+            .map(|text| {
+                request_hash(&json!({
+                    "method": "embed",
+                    "provider": self.provider,
+                    "model": self.model,
+                    "task": format!("{:?}", task),
+                    "dimensions": dimensions,
+                    "text": text,
+                }))
+            })
+            .collect();
+        let mut cached = self.cache.get_embeddings(&keys).await;
+
+        let misses: Vec<usize> = (0..texts.len())
+            .filter(|&i| !cached.contains_key(&keys[i]))
+            .collect();
+        let mut model = self.model.clone();
+        let mut usage = TokenUsage::default();
+        if !misses.is_empty() {
+            let miss_texts: Vec<String> = misses.iter().map(|&i| texts[i].clone()).collect();
+            let fresh = self
+                .inner
+                .embed_batch(&miss_texts, task, dimensions)
+                .await?;
+            for (&i, vector) in misses.iter().zip(fresh.vectors) {
+                self.cache
+                    .put_embedding(&keys[i], &fresh.model, &vector)
+                    .await;
+                cached.insert(keys[i].clone(), vector);
+            }
+            model = fresh.model;
+            usage = fresh.usage;
+        }
+
+        let vectors = keys
+            .iter()
+            .map(|key| {
+                cached.remove(key).ok_or_else(|| {
+                    LlmError::malformed("provider returned fewer embeddings than requested", "")
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Embeddings {
+            vectors,
+            model,
+            usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Answers every call and records what it was asked
+    #[derive(Default)]
+    struct Counting {
+        prompts: Mutex<Vec<String>>,
+        texts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Counting {
+        async fn generate_text(
+            &self,
+            prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok(Generation {
+                text: format!("reply to {}", prompt),
+                model: "counting".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: 10,
+                    completion_tokens: 5,
+                },
+            })
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            _schema: Option<&Value>,
+            params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            self.generate_text(prompt, params).await
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for Counting {
+        async fn embed_batch(
+            &self,
+            texts: &[String],
+            _task: EmbeddingTask,
+            _dimensions: Option<usize>,
+        ) -> Result<Embeddings, LlmError> {
+            self.texts.lock().unwrap().extend(texts.iter().cloned());
+            Ok(Embeddings {
+                vectors: texts.iter().map(|t| vec![t.len() as f32]).collect(),
+                model: "counting".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: texts.len() as u32,
+                    completion_tokens: 0,
+                },
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_calls_are_served_from_cache() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+        let cache = ResponseCache::new(pool.clone(), &CacheConfig::default()).unwrap();
+
+        // A provider name of its own keeps other tests' entries out of the way
+        let provider = format!("cache-test-{}", uuid::Uuid::new_v4());
+        let inner = Arc::new(Counting::default());
+        let llm = cache.wrap_llm(inner.clone(), provider.clone(), "counting".to_string());
+        let embedder = cache.wrap_embedder(inner.clone(), provider.clone(), "counting".to_string());
+
+        let params = GenerationParams::default();
+        let first = llm.generate_text("Hello", &params).await.unwrap();
+        let second = llm.generate_text("Hello", &params).await.unwrap();
+        assert_eq!(second.text, first.text);
+        assert_eq!(second.usage, TokenUsage::default());
+        llm.generate_json("Hello", None, &params).await.unwrap();
+        let hotter = GenerationParams {
+            temperature: Some(0.9),
+            ..GenerationParams::default()
+        };
+        llm.generate_text("Hello", &hotter).await.unwrap();
+        let bypass = GenerationParams {
+            bypass_cache: true,
+            ..GenerationParams::default()
+        };
+        llm.generate_text("Hello", &bypass).await.unwrap();
+        assert_eq!(inner.prompts.lock().unwrap().len(), 4);
+
+        let texts = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        embedder
+            .embed_batch(&texts(&["a", "bb"]), EmbeddingTask::RetrievalDocument, None)
+            .await
+            .unwrap();
+        let embeddings = embedder
+            .embed_batch(
+                &texts(&["bb", "ccc", "a"]),
+                EmbeddingTask::RetrievalDocument,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(embeddings.vectors, vec![vec![2.0], vec![3.0], vec![1.0]]);
+        assert_eq!(embeddings.usage.prompt_tokens, 1);
+        assert_eq!(*inner.texts.lock().unwrap(), texts(&["a", "bb", "ccc"]));
+
+        sqlx::query!(
+            "DELETE FROM llm_cache WHERE model = 'counting' AND created_at > NOW() - INTERVAL '1 hour'"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+}
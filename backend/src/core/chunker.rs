@@ -0,0 +1,138 @@
+// Splits long materials into overlapping, paragraph-aware windows so each
+// extraction prompt stays within the model's context
+use crate::core::config::ChunkConfig;
+
+// Rough token count (~4 characters per token for English text)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+// Chunks never split a paragraph unless the paragraph alone exceeds
+// `max_tokens`; consecutive chunks share trailing paragraphs worth up to
+// `overlap_tokens` so questions on a boundary appear whole in one of them.
+pub fn chunk_text(text: &str, config: &ChunkConfig) -> Vec<String> {
+    let max_tokens = config.max_tokens.max(1);
+    let overlap_tokens = config.overlap_tokens.min(max_tokens / 2);
+
+    let paragraphs: Vec<String> = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .flat_map(|p| split_oversized(p, max_tokens))
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut current_tokens = 0;
+
+    for paragraph in &paragraphs {
+        // +1 for the blank line joining it to the previous paragraph
+        let tokens = estimate_tokens(paragraph) + 1;
+        if !current.is_empty() && current_tokens + tokens > max_tokens {
+            chunks.push(current.join("\n\n"));
+
+            // Carry the tail of this chunk into the next one
+            let mut carried: Vec<&str> = Vec::new();
+            let mut carried_tokens = 0;
+            for previous in current.iter().rev() {
+                let t = estimate_tokens(previous) + 1;
+                if carried_tokens + t > overlap_tokens || carried_tokens + t + tokens > max_tokens {
+                    break;
+                }
+                carried.insert(0, previous);
+                carried_tokens += t;
+            }
+            current = carried;
+            current_tokens = carried_tokens;
+        }
+        current.push(paragraph);
+        current_tokens += tokens;
+    }
+
+    if !current.is_empty() {
+        chunks.push(current.join("\n\n"));
+    }
+    chunks
+}
+
+// Break a paragraph longer than `max_tokens` on sentence ends, then on words
+fn split_oversized(paragraph: &str, max_tokens: usize) -> Vec<String> {
+    if estimate_tokens(paragraph) <= max_tokens {
+        return vec![paragraph.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for word in paragraph.split_whitespace() {
+        let tokens = estimate_tokens(&current);
+        let full = tokens + estimate_tokens(word) + 1 > max_tokens;
+        // Prefer ending a piece on a sentence once it is mostly full
+        let sentence_break = current.ends_with(['.', '?', '!']) && tokens >= max_tokens * 3 / 4;
+        if !current.is_empty() && (full || sentence_break) {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_is_one_chunk() {
+        let chunks = chunk_text("One.\n\nTwo.", &ChunkConfig::default());
+        assert_eq!(chunks, vec!["One.\n\nTwo.".to_string()]);
+    }
+
+    #[test]
+    fn test_chunks_respect_size_and_overlap() {
+        // 10 paragraphs of ~25 tokens each
+        let paragraphs: Vec<String> = (0..10)
+            .map(|i| {
+                format!("Paragraph {} {}", i, "word ".repeat(18))
+                    .trim()
+                    .to_string()
+            })
+            .collect();
+        let text = paragraphs.join("\n\n");
+        let config = ChunkConfig {
+            max_tokens: 80,
+            overlap_tokens: 30,
+        };
+
+        let chunks = chunk_text(&text, &config);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(estimate_tokens(chunk) <= config.max_tokens, "{}", chunk);
+        }
+        // Every paragraph is present, and each chunk starts with the last
+        // paragraph of the one before it
+        for paragraph in &paragraphs {
+            assert!(chunks.iter().any(|c| c.contains(paragraph.as_str())));
+        }
+        for pair in chunks.windows(2) {
+            let last = pair[0].split("\n\n").last().unwrap();
+            assert!(pair[1].starts_with(last));
+        }
+    }
+
+    #[test]
+    fn test_oversized_paragraph_is_split() {
+        let text = "This is a sentence. ".repeat(100);
+        let config = ChunkConfig {
+            max_tokens: 50,
+            overlap_tokens: 0,
+        };
+        let chunks = chunk_text(&text, &config);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| estimate_tokens(c) <= 50));
+    }
+}
@@ -0,0 +1,294 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+use std::io::{Cursor, Read};
+
+// Text extraction for uploaded practice books
+
+// Largest DOCX body (`word/document.xml`) read once decompressed. A small
+// upload can inflate to gigabytes, which the request body limit does not see.
+pub const MAX_DOCX_XML_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Pdf,
+    Docx,
+    Text,
+}
+
+impl DocumentKind {
+    // Sniff the file type from its magic bytes, falling back to the declared
+    // content type and file extension
+    pub fn detect(filename: &str, content_type: Option<&str>, bytes: &[u8]) -> Option<Self> {
+        let filename = filename.to_lowercase();
+        let content_type = content_type.unwrap_or_default();
+
+        if bytes.starts_with(b"%PDF") {
+            return Some(DocumentKind::Pdf);
+        }
+        if bytes.starts_with(b"PK\x03\x04")
+            && (filename.ends_with(".docx") || content_type.contains("wordprocessingml"))
+        {
+            return Some(DocumentKind::Docx);
+        }
+        let is_text = content_type.starts_with("text/")
+            || filename.ends_with(".txt")
+            || filename.ends_with(".md");
+        if is_text && std::str::from_utf8(bytes).is_ok() {
+            return Some(DocumentKind::Text);
+        }
+        None
+    }
+
+    pub fn source_type(&self) -> &'static str {
+        match self {
+            DocumentKind::Pdf => "pdf",
+            DocumentKind::Docx => "docx",
+            DocumentKind::Text => "text",
+        }
+    }
+}
+
+// A page (PDF) or heading-delimited section (DOCX)
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub label: Option<String>,
+    pub text: String,
+}
+
+// Where each section starts in the rendered text, stored with the file
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SectionOffset {
+    pub label: Option<String>,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractedDocument {
+    pub sections: Vec<Section>,
+}
+
+impl ExtractedDocument {
+    // Render as plain text, each labelled section introduced by a "[label]"
+    // paragraph so page/section boundaries survive into the prompt
+    pub fn render(&self) -> (String, Vec<SectionOffset>) {
+        let mut text = String::new();
+        let mut offsets = Vec::new();
+
+        for section in &self.sections {
+            let body = section.text.trim();
+            if body.is_empty() {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            let offset = text.len();
+            if let Some(label) = &section.label {
+                text.push_str(&format!("[{}]\n\n", label));
+            }
+            text.push_str(body);
+            offsets.push(SectionOffset {
+                label: section.label.clone(),
+                offset,
+                length: text.len() - offset,
+            });
+        }
+
+        (text, offsets)
+    }
+}
+
+pub fn extract(kind: DocumentKind, bytes: &[u8]) -> Result<ExtractedDocument, String> {
+    let sections = match kind {
+        DocumentKind::Pdf => extract_pdf(bytes)?,
+        DocumentKind::Docx => extract_docx(bytes)?,
+        DocumentKind::Text => vec![Section {
+            label: None,
+            text: String::from_utf8_lossy(bytes).into_owned(),
+        }],
+    };
+    Ok(ExtractedDocument { sections })
+}
+
+fn extract_pdf(bytes: &[u8]) -> Result<Vec<Section>, String> {
+    // pdf-extract panics on some malformed files instead of returning an error
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| "PDF could not be parsed".to_string())?
+        .map_err(|e| format!("PDF could not be parsed: {}", e))?;
+
+    Ok(pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| Section {
+            label: Some(format!("Page {}", i + 1)),
+            text,
+        })
+        .collect())
+}
+
+fn extract_docx(bytes: &[u8]) -> Result<Vec<Section>, String> {
+    let xml = docx_xml(bytes, MAX_DOCX_XML_BYTES)?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut sections = vec![Section {
+        label: None,
+        text: String::new(),
+    }];
+    let mut paragraph = String::new();
+    let mut is_heading = false;
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"p" => {
+                    paragraph.clear();
+                    is_heading = false;
+                }
+                b"pStyle" => {
+                    if let Ok(Some(attr)) = e.try_get_attribute("w:val") {
+                        let style = attr.unescape_value().unwrap_or_default();
+                        is_heading = style.starts_with("Heading") || style == "Title";
+                    }
+                }
+                b"t" => in_text = true,
+                b"tab" => paragraph.push('\t'),
+                b"br" | b"cr" => paragraph.push('\n'),
+                _ => {}
+            },
+            Ok(Event::Text(t)) if in_text => {
+                let text = t
+                    .unescape()
+                    .map_err(|e| format!("DOCX text could not be decoded: {}", e))?;
+                paragraph.push_str(&text);
+            }
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let text = paragraph.trim();
+                    if is_heading && !text.is_empty() {
+                        sections.push(Section {
+                            label: Some(text.to_string()),
+                            text: String::new(),
+                        });
+                    } else if !text.is_empty() {
+                        let current = sections.last_mut().expect("at least one section");
+                        if !current.text.is_empty() {
+                            current.text.push_str("\n\n");
+                        }
+                        current.text.push_str(text);
+                    }
+                    paragraph.clear();
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("DOCX is malformed: {}", e)),
+            _ => {}
+        }
+    }
+
+    Ok(sections)
+}
+
+// The document body, refused when it inflates past `limit` bytes. The size
+// in the zip header is checked first but can lie, so reading stops at the
+// limit too.
+fn docx_xml(bytes: &[u8], limit: u64) -> Result<String, String> {
+    let too_large = || {
+        format!(
+            "DOCX document body is larger than {} MiB",
+            limit / (1024 * 1024)
+        )
+    };
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| format!("DOCX could not be opened: {}", e))?;
+    let file = archive
+        .by_name("word/document.xml")
+        .map_err(|e| format!("DOCX has no document body: {}", e))?;
+    if file.size() > limit {
+        return Err(too_large());
+    }
+    let mut xml = String::new();
+    file.take(limit + 1)
+        .read_to_string(&mut xml)
+        .map_err(|e| format!("DOCX could not be read: {}", e))?;
+    if xml.len() as u64 > limit {
+        return Err(too_large());
+    }
+    Ok(xml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn build_docx(document_xml: &str) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut buffer);
+        writer
+            .start_file(
+                "word/document.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(document_xml.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_docx_sections_follow_headings() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+              <w:p><w:r><w:t>CU-TEP Practice Book</w:t></w:r></w:p>
+              <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Reading Passage 1</w:t></w:r></w:p>
+              <w:p><w:r><w:t xml:space="preserve">Bees are </w:t></w:r><w:r><w:t>important &amp; busy.</w:t></w:r></w:p>
+              <w:p><w:r><w:t>1. What is the passage about?</w:t></w:r></w:p>
+            </w:body></w:document>"#;
+        let bytes = build_docx(xml);
+
+        let kind = DocumentKind::detect("book.docx", None, &bytes);
+        assert_eq!(kind, Some(DocumentKind::Docx));
+
+        let document = extract(DocumentKind::Docx, &bytes).expect("DOCX extraction failed");
+        let (text, offsets) = document.render();
+        assert_eq!(
+            text,
+            "CU-TEP Practice Book\n\n[Reading Passage 1]\n\nBees are important & busy.\n\n1. What is the passage about?"
+        );
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets[1].label.as_deref(), Some("Reading Passage 1"));
+        assert!(text[offsets[1].offset..].starts_with("[Reading Passage 1]"));
+    }
+
+    #[test]
+    fn test_docx_body_over_the_limit_is_refused() {
+        let xml = format!("<w:document>{}</w:document>", "a".repeat(4096));
+        let bytes = build_docx(&xml);
+        assert!(bytes.len() < 1024);
+
+        assert_eq!(docx_xml(&bytes, 8192).unwrap(), xml);
+        let error = docx_xml(&bytes, 1024).unwrap_err();
+        assert!(
+            error.starts_with("DOCX document body is larger than"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_detect_rejects_unknown_binary() {
+        assert_eq!(
+            DocumentKind::detect("notes.txt", Some("text/plain"), b"hello"),
+            Some(DocumentKind::Text)
+        );
+        assert_eq!(
+            DocumentKind::detect("image.png", None, &[0x89, b'P', b'N', b'G']),
+            None
+        );
+    }
+}
@@ -1,11 +1,9 @@
-use crate::core::traits::{ExamGenerationEngine, PersonalizationEngine, VectorAccessor};
-use serde_json::Value;
+use crate::core::traits::{ExamGenerationEngine, ExamStream, PersonalizationEngine};
 
 // The Stable Manager
 pub struct EducationManager {
     exam_engine: Box<dyn ExamGenerationEngine>,
     personalization_engine: Box<dyn PersonalizationEngine>,
-    vector_accessor: Box<dyn VectorAccessor>,
 }
 
 impl EducationManager {
@@ -13,35 +11,38 @@ impl EducationManager {
     pub fn new(
         exam_engine: Box<dyn ExamGenerationEngine>,
         personalization_engine: Box<dyn PersonalizationEngine>,
-        vector_accessor: Box<dyn VectorAccessor>,
     ) -> Self {
         Self {
             exam_engine,
             personalization_engine,
-            vector_accessor,
         }
     }
 
-    // The workflow logic (Stable)
-    pub async fn generate_personalized_exam(&self, user_id: &str) -> Result<Value, String> {
-        // 1. Identify what the user needs (Personalization Engine)
-        let weak_points = self
-            .personalization_engine
-            .determine_weak_points(user_id)
-            .await?;
+    // Picks a topic and streams its exam for the SSE endpoint. An explicit
+    // `topic` skips personalization.
+    pub async fn stream_personalized_exam(
+        &self,
+        user_id: &str,
+        topic: Option<&str>,
+        difficulty: &str,
+    ) -> Result<(String, ExamStream), String> {
+        let topic = match topic {
+            Some(topic) => topic.to_string(),
+            None => self
+                .personalization_engine
+                .determine_weak_points(user_id)
+                .await?
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| "general".to_string()),
+        };
 
-        // 2. Decide on a topic (Logic in Manager, or delegate to Engine)
-        let topic = weak_points
-            .first()
-            .unwrap_or(&"general".to_string())
-            .clone();
+        let exam = self
+            .exam_engine
+            .stream_exam(&topic, difficulty)
+            .await
+            .map_err(|e| e.to_string())?;
 
-        // 3. (Optional) Find similar past questions (Vector Accessor)
-        // let _examples = self.vector_accessor.find_similar_questions(&[], 3).await?;
-
-        // 4. Generate new content (Exam Engine)
-        let exam = self.exam_engine.generate_exam(&topic, "medium").await?;
-
-        Ok(exam)
+        Ok((topic, exam))
     }
 }
@@ -1,35 +1,239 @@
-use crate::core::gemini_client::GeminiClient;
-use crate::core::traits::{ExamGenerationEngine, PersonalizationEngine};
+use crate::core::config::GenerationParams;
+use crate::core::llm_error::LlmError;
+use crate::core::prompts::{self, PromptRegistry};
+use crate::core::questions::{PassageDraft, Question, QuestionDraft};
+use crate::core::readability;
+use crate::core::streaming::ArrayItems;
+use crate::core::structured::{parse_and_validate, schema_for};
+use crate::core::traits::{
+    ChunkStream, ExamGenerationEngine, ExamItem, ExamStream, LlmProvider, PersonalizationEngine,
+};
+use crate::core::verification::Verifier;
 use async_trait::async_trait;
-use serde_json::Value;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
 
 // --- Exam Generation Engine ---
 
-pub struct GeminiExamEngine {
-    client: GeminiClient,
+pub struct LlmExamEngine {
+    llm: Arc<dyn LlmProvider>,
+    params: GenerationParams,
+    prompts: PromptRegistry,
+    key_check: Option<Arc<KeyCheck>>,
 }
 
-impl GeminiExamEngine {
-    pub fn new(client: GeminiClient) -> Self {
-        Self { client }
+// Answer keys of generated questions are verified before they are served;
+// a question below `min_confidence` is left out
+struct KeyCheck {
+    verifier: Verifier,
+    min_confidence: f64,
+}
+
+// Shape the model must return for a generated exam. Passages come first (the
+// model writes keys in order), so a set's passage is written, and streamed,
+// before its questions.
+#[derive(Deserialize, Serialize, JsonSchema, Debug)]
+pub struct GeneratedExam {
+    pub passages: Vec<PassageDraft>,
+    pub questions: Vec<QuestionDraft>,
+}
+
+impl LlmExamEngine {
+    pub fn new(
+        llm: Arc<dyn LlmProvider>,
+        params: GenerationParams,
+        prompts: PromptRegistry,
+    ) -> Self {
+        Self {
+            llm,
+            params,
+            prompts,
+            key_check: None,
+        }
+    }
+
+    pub fn with_verifier(mut self, verifier: Verifier, min_confidence: f64) -> Self {
+        self.key_check = Some(Arc::new(KeyCheck {
+            verifier,
+            min_confidence,
+        }));
+        self
     }
 }
 
 #[async_trait]
-impl ExamGenerationEngine for GeminiExamEngine {
-    async fn generate_exam(&self, topic: &str, difficulty: &str) -> Result<Value, String> {
-        let prompt = format!(
-            "Generate a {} difficulty exam question for topic: {}. Return as JSON.",
-            difficulty, topic
-        );
-        match self.client.generate_json(&prompt).await {
-            Ok(json_str) => {
-                // naive parsing for MVP
-                serde_json::from_str(&json_str).map_err(|e| e.to_string())
+impl ExamGenerationEngine for LlmExamEngine {
+    // There is no repair round-trip while streaming: an item that does not
+    // match the schema, or a reply that is not a valid exam once complete,
+    // ends the stream with an error. A well-formed question that does not fit
+    // its CU-TEP type is dropped.
+    async fn stream_exam(&self, topic: &str, difficulty: &str) -> Result<ExamStream, LlmError> {
+        let template =
+            self.prompts
+                .select(prompts::EXAM, &self.params.prompt_versions, rand::random());
+        let prompt = template.render(&[("topic", topic), ("difficulty", difficulty)]);
+        let stream = self
+            .llm
+            .stream_json(&prompt, Some(&schema_for::<GeneratedExam>()), &self.params)
+            .await?;
+        Ok(ExamStream {
+            prompt_version: template.id(),
+            items: exam_items(stream.chunks, self.key_check.clone()),
+        })
+    }
+}
+
+// Each passage and question of a streamed exam reply, validated as soon as
+// it is complete
+fn exam_items(
+    chunks: ChunkStream,
+    key_check: Option<Arc<KeyCheck>>,
+) -> BoxStream<'static, Result<ExamItem, LlmError>> {
+    let state = Some((chunks, ArrayItems::default(), Vec::new(), key_check));
+    stream::unfold(state, |state| async move {
+        let (mut chunks, mut items, mut passages, key_check) = state?;
+        match chunks.next().await {
+            Some(Ok(chunk)) => {
+                let mut parsed = Vec::new();
+                for (key, item) in items.push(&chunk.text) {
+                    match parse_item(&key, &item, &mut passages, key_check.as_deref()).await {
+                        Ok(Some(exam_item)) => parsed.push(Ok(exam_item)),
+                        Ok(None) => {}
+                        Err(e) => {
+                            parsed.push(Err(e));
+                            return Some((parsed, None));
+                        }
+                    }
+                }
+                Some((parsed, Some((chunks, items, passages, key_check))))
+            }
+            Some(Err(e)) => Some((vec![Err(e)], None)),
+            None => {
+                let schema = schema_for::<GeneratedExam>();
+                match parse_and_validate::<GeneratedExam>(items.text(), &schema) {
+                    Ok(_) => None,
+                    Err(errors) => {
+                        let error = LlmError::malformed(
+                            format!("reply does not match schema: {}", errors.join("; ")),
+                            items.text().to_string(),
+                        );
+                        Some((vec![Err(error)], None))
+                    }
+                }
             }
-            Err(e) => Err(e.to_string()),
         }
+    })
+    .flat_map(stream::iter)
+    .boxed()
+}
+
+// `None` for a question that was rejected. Questions are checked against the
+// passages received before them.
+async fn parse_item(
+    key: &str,
+    item: &str,
+    passages: &mut Vec<PassageDraft>,
+    key_check: Option<&KeyCheck>,
+) -> Result<Option<ExamItem>, LlmError> {
+    match key {
+        "passages" => {
+            let passage: PassageDraft = parse_draft(item)?;
+            let value = passage_value(passages.len(), &passage);
+            passages.push(passage);
+            Ok(Some(ExamItem::Passage(value)))
+        }
+        "questions" => {
+            let draft: QuestionDraft = parse_draft(item)?;
+            let passage = draft.passage;
+            let Some(question) = checked(draft, passages) else {
+                return Ok(None);
+            };
+            let value = served(question, passage, passages, key_check).await;
+            Ok(value.map(ExamItem::Question))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_draft<T: JsonSchema + DeserializeOwned>(item: &str) -> Result<T, LlmError> {
+    parse_and_validate(item, &schema_for::<T>()).map_err(|errors| {
+        LlmError::malformed(
+            format!("item does not match schema: {}", errors.join("; ")),
+            item.to_string(),
+        )
+    })
+}
+
+// Generated questions are not stored, so one that does not fit its type is
+// only logged and left out of the exam. Unlike an extracted item, a
+// generated one must come with its key.
+fn checked(draft: QuestionDraft, passages: &[PassageDraft]) -> Option<Question> {
+    draft
+        .validate(passages)
+        .and_then(|question| match question.answer() {
+            Some(_) => Ok(question),
+            None => Err(vec!["answer: a generated item needs a key".to_string()]),
+        })
+        .map_err(|errors| eprintln!("Rejected generated question: {}", errors.join("; ")))
+        .ok()
+}
+
+// The question as served, with the outcome of its key check when answer keys
+// are verified. `None` when the check is not confident enough, or could not
+// be made: one failed check drops its question, not the whole exam.
+async fn served(
+    question: Question,
+    passage: Option<usize>,
+    passages: &[PassageDraft],
+    key_check: Option<&KeyCheck>,
+) -> Option<Value> {
+    let mut value = question_value(&question, passage);
+    if let Some(check) = key_check {
+        let text = passage.map(|i| passages[i].text.as_str());
+        // Generated questions have no id; each draws its own prompt version
+        let verification = match check.verifier.verify(&question, text, rand::random()).await {
+            Ok(verification) => verification,
+            Err(e) => {
+                eprintln!(
+                    "Rejected generated question: answer key not verified: {}",
+                    e
+                );
+                return None;
+            }
+        };
+        if verification.confidence < check.min_confidence {
+            eprintln!(
+                "Rejected generated question: answer key {} (confidence {})",
+                verification.status.as_str(),
+                verification.confidence
+            );
+            return None;
+        }
+        value["verification_status"] = json!(verification.status);
+        value["answer_confidence"] = json!(verification.confidence);
     }
+    Some(value)
+}
+
+// Questions refer to passages by `index`
+fn passage_value(index: usize, passage: &PassageDraft) -> Value {
+    json!({
+        "index": index,
+        "kind": passage.kind,
+        "label": passage.label,
+        "text": passage.text,
+        "readability": readability::measure(&passage.text),
+    })
+}
+
+fn question_value(question: &Question, passage: Option<usize>) -> Value {
+    let mut value = serde_json::to_value(question).expect("question serializes");
+    value["passage"] = json!(passage);
+    value
 }
 
 // --- Personalization Engine ---
@@ -46,3 +250,198 @@ impl PersonalizationEngine for RandomPersonalizationEngine {
         ])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::traits::{Generation, GenerationChunk, GenerationStream, TokenUsage};
+
+    // Streams a fixed reply a few bytes at a time
+    struct Streaming {
+        reply: &'static str,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Streaming {
+        async fn generate_text(
+            &self,
+            _prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!("only streaming is used")
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            _schema: Option<&Value>,
+            params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            self.generate_text(prompt, params).await
+        }
+
+        async fn stream_json(
+            &self,
+            _prompt: &str,
+            _schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<GenerationStream, LlmError> {
+            let pieces: Vec<Result<GenerationChunk, LlmError>> = self
+                .reply
+                .as_bytes()
+                .chunks(9)
+                .map(|piece| {
+                    Ok(GenerationChunk {
+                        text: String::from_utf8(piece.to_vec()).unwrap(),
+                        usage: None,
+                    })
+                })
+                .chain([Ok(GenerationChunk {
+                    text: String::new(),
+                    usage: Some(TokenUsage::default()),
+                })])
+                .collect();
+            Ok(GenerationStream {
+                model: "test".to_string(),
+                chunks: stream::iter(pieces).boxed(),
+            })
+        }
+    }
+
+    // Each item as ("passage" or "question", value)
+    async fn streamed(
+        reply: &'static str,
+    ) -> (String, Vec<Result<(&'static str, Value), LlmError>>) {
+        let engine = LlmExamEngine::new(
+            Arc::new(Streaming { reply }),
+            GenerationParams::default(),
+            PromptRegistry::builtin(),
+        );
+        let exam = engine.stream_exam("reading", "easy").await.unwrap();
+        let items = exam
+            .items
+            .map(|item| {
+                item.map(|item| match item {
+                    ExamItem::Passage(value) => ("passage", value),
+                    ExamItem::Question(value) => ("question", value),
+                })
+            })
+            .collect()
+            .await;
+        (exam.prompt_version, items)
+    }
+
+    #[tokio::test]
+    async fn test_exam_items_are_streamed_and_validated() {
+        let (version, items) = streamed(
+            r#"{"passages": [{"kind": "reading", "label": null, "text": "Bees are busy."}],
+            "questions": [
+                {"type": "listening", "stem": "Q1", "options": ["a", "b"], "answer": "A"},
+                {"type": "sentence_completion", "stem": "Q2 has no blank", "options": ["a", "b"], "answer": "A"},
+                {"type": "reading_comprehension", "stem": "Q3", "options": ["c", "d"], "answer": "B", "passage": 0},
+                {"type": "reading_comprehension", "stem": "Q4", "options": ["c", "d"], "answer": "B", "passage": 1}
+            ]}"#,
+        )
+        .await;
+        assert_eq!(version, "exam@v2");
+        // The cloze item without a blank and the question about a missing
+        // passage are dropped
+        let items: Vec<(&str, Value)> = items.into_iter().map(Result::unwrap).collect();
+        let kinds: Vec<&str> = items.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, ["passage", "question", "question"]);
+        assert_eq!(items[0].1["index"], 0);
+        assert_eq!(items[0].1["readability"]["word_count"], 3);
+        assert_eq!(items[2].1["type"], "reading_comprehension");
+        assert_eq!(items[2].1["passage"], 0);
+
+        // A question missing its answer ends the stream after the valid one
+        let (_, items) = streamed(
+            r#"{"passages": [], "questions": [
+                {"type": "listening", "stem": "Q1", "options": ["a", "b"], "answer": "A"},
+                {"type": "listening", "stem": "Q2", "options": ["a", "b"]},
+                {"type": "listening", "stem": "Q3", "options": ["a", "b"], "answer": "A"}
+            ]}"#,
+        )
+        .await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(matches!(items[1], Err(LlmError::MalformedResponse { .. })));
+
+        // So does a reply cut off before the exam is complete
+        let (_, items) = streamed(
+            r#"{"passages": [], "questions": [{"type": "listening", "stem": "Q1", "options": ["a", "b"], "answer": "A"}"#,
+        )
+        .await;
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+
+    // Writes `exam` and, when asked to verify, always answers A; the check of
+    // Q4 fails
+    struct AnswersA {
+        exam: &'static str,
+    }
+
+    #[async_trait]
+    impl LlmProvider for AnswersA {
+        async fn generate_text(
+            &self,
+            _prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!("only JSON is used")
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            let verifying = schema.is_some_and(|s| s["properties"].get("also_correct").is_some());
+            if verifying && prompt.contains("Q4") {
+                return Err(LlmError::Network {
+                    message: "connection reset".to_string(),
+                });
+            }
+            let text = if verifying {
+                r#"{"reasoning": "", "answer": "A", "also_correct": []}"#
+            } else {
+                self.exam
+            };
+            Ok(Generation {
+                text: text.to_string(),
+                model: "test".to_string(),
+                usage: TokenUsage::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generated_answer_keys_are_verified() {
+        let llm = Arc::new(AnswersA {
+            exam: r#"{"passages": [], "questions": [
+                {"type": "listening", "stem": "Q1", "options": ["a", "b"], "answer": "A"},
+                {"type": "listening", "stem": "Q2", "options": ["a", "b"], "answer": "B"},
+                {"type": "listening", "stem": "Q3", "options": ["a", "b"], "answer": ""},
+                {"type": "listening", "stem": "Q4", "options": ["a", "b"], "answer": "A"}
+            ]}"#,
+        });
+        let prompts = PromptRegistry::builtin();
+        let verifier = Verifier::new(llm.clone(), GenerationParams::default(), &prompts, 1);
+        let engine = LlmExamEngine::new(llm, GenerationParams::default(), prompts)
+            .with_verifier(verifier, 0.5);
+
+        // Q2's key disagrees with the model, Q3 has none and Q4 could not be
+        // checked; none of them ends the exam
+        let exam = engine.stream_exam("listening", "easy").await.unwrap();
+        let items: Vec<_> = exam.items.collect().await;
+        assert_eq!(items.len(), 1);
+        let Ok(ExamItem::Question(question)) = &items[0] else {
+            panic!("not a question: {:?}", items[0].as_ref().err());
+        };
+        assert_eq!(question["stem"], "Q1");
+        assert_eq!(question["verification_status"], "verified");
+        assert_eq!(question["answer_confidence"], 1.0);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::core::cache::{generation_request, request_hash};
+use crate::core::config::{FixtureConfig, FixtureMode, GenerationParams};
+use crate::core::llm_error::LlmError;
+use crate::core::streaming;
+use crate::core::traits::{
+    EmbeddingProvider, EmbeddingTask, Embeddings, Generation, GenerationStream, LlmProvider,
+    TokenUsage,
+};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Provider calls saved as `<method>-<request hash>.json` files holding the
+// request and the response, so tests can run offline against real replies.
+// Record mode calls the provider and writes each pair; replay mode answers
+// from the files alone and fails on a request that was never recorded.
+#[derive(Clone)]
+pub struct FixtureStore {
+    dir: PathBuf,
+    mode: FixtureMode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Fixture<T> {
+    request: Value,
+    response: T,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedGeneration {
+    text: String,
+    model: String,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedEmbeddings {
+    vectors: Vec<Vec<f32>>,
+    model: String,
+    prompt_tokens: u32,
+}
+
+impl FixtureStore {
+    // `None` when fixtures are off
+    pub fn new(config: &FixtureConfig) -> Option<Self> {
+        (config.mode != FixtureMode::Off).then(|| Self {
+            dir: PathBuf::from(&config.dir),
+            mode: config.mode,
+        })
+    }
+
+    // `provider` and `default_model` go into the request hash, as for the
+    // response cache
+    pub fn wrap_llm(
+        &self,
+        inner: Arc<dyn LlmProvider>,
+        provider: String,
+        default_model: String,
+    ) -> Arc<dyn LlmProvider> {
+        Arc::new(FixtureLlm {
+            inner,
+            store: self.clone(),
+            provider,
+            default_model,
+        })
+    }
+
+    pub fn wrap_embedder(
+        &self,
+        inner: Arc<dyn EmbeddingProvider>,
+        provider: String,
+        model: String,
+    ) -> Arc<dyn EmbeddingProvider> {
+        Arc::new(FixtureEmbedder {
+            inner,
+            store: self.clone(),
+            provider,
+            model,
+        })
+    }
+
+    fn path(&self, method: &str, request: &Value) -> PathBuf {
+        self.dir
+            .join(format!("{}-{}.json", method, request_hash(request)))
+    }
+
+    async fn load<T: DeserializeOwned>(&self, path: &PathBuf) -> Result<T, LlmError> {
+        let body = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|_| LlmError::MissingFixture {
+                path: path.display().to_string(),
+            })?;
+        let fixture: Fixture<T> = serde_json::from_str(&body).map_err(|e| {
+            LlmError::malformed(format!("fixture {}: {}", path.display(), e), body.clone())
+        })?;
+        Ok(fixture.response)
+    }
+
+    // A fixture that cannot be written is logged; the call still succeeds
+    async fn save<T: Serialize>(&self, path: &PathBuf, request: Value, response: T) {
+        let fixture = Fixture { request, response };
+        let result = match tokio::fs::create_dir_all(&self.dir).await {
+            Ok(()) => {
+                let body = serde_json::to_string_pretty(&fixture).expect("fixture serializes");
+                tokio::fs::write(path, body).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to write fixture {}: {}", path.display(), e);
+        }
+    }
+}
+
+struct FixtureLlm {
+    inner: Arc<dyn LlmProvider>,
+    store: FixtureStore,
+    provider: String,
+    default_model: String,
+}
+
+impl FixtureLlm {
+    // `call` is only awaited when recording
+    async fn replay_or_record(
+        &self,
+        method: &str,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+        call: impl Future<Output = Result<Generation, LlmError>> + Send,
+    ) -> Result<Generation, LlmError> {
+        let request = generation_request(
+            method,
+            &self.provider,
+            &self.default_model,
+            prompt,
+            schema,
+            params,
+        );
+        let path = self.store.path(method, &request);
+
+        if self.store.mode == FixtureMode::Replay {
+            let recorded: RecordedGeneration = self.store.load(&path).await?;
+            return Ok(Generation {
+                text: recorded.text,
+                model: recorded.model,
+                usage: TokenUsage {
+                    prompt_tokens: recorded.prompt_tokens,
+                    completion_tokens: recorded.completion_tokens,
+                },
+            });
+        }
+
+        let generation = call.await?;
+        let recorded = RecordedGeneration {
+            text: generation.text.clone(),
+            model: generation.model.clone(),
+            prompt_tokens: generation.usage.prompt_tokens,
+            completion_tokens: generation.usage.completion_tokens,
+        };
+        self.store.save(&path, request, recorded).await;
+        Ok(generation)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FixtureLlm {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let call = self.inner.generate_text(prompt, params);
+        self.replay_or_record("generate_text", prompt, None, params, call)
+            .await
+    }
+
+    async fn generate_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let call = self.inner.generate_json(prompt, schema, params);
+        self.replay_or_record("generate_json", prompt, schema, params, call)
+            .await
+    }
+
+    // Uses the `generate_json` fixture: replayed as one chunk, recorded once
+    // the streamed reply is complete
+    async fn stream_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<GenerationStream, LlmError> {
+        let request = generation_request(
+            "generate_json",
+            &self.provider,
+            &self.default_model,
+            prompt,
+            schema,
+            params,
+        );
+        let path = self.store.path("generate_json", &request);
+
+        if self.store.mode == FixtureMode::Replay {
+            let recorded: RecordedGeneration = self.store.load(&path).await?;
+            let usage = TokenUsage {
+                prompt_tokens: recorded.prompt_tokens,
+                completion_tokens: recorded.completion_tokens,
+            };
+            return Ok(GenerationStream {
+                model: recorded.model,
+                chunks: streaming::single_chunk(recorded.text, Some(usage)),
+            });
+        }
+
+        let stream = self.inner.stream_json(prompt, schema, params).await?;
+        let (store, model) = (self.store.clone(), stream.model.clone());
+        let chunks = streaming::on_complete(stream.chunks, move |text, usage| async move {
+            let usage = usage.unwrap_or_default();
+            let recorded = RecordedGeneration {
+                text,
+                model,
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            };
+            store.save(&path, request, recorded).await;
+        });
+        Ok(GenerationStream {
+            model: stream.model,
+            chunks,
+        })
+    }
+}
+
+struct FixtureEmbedder {
+    inner: Arc<dyn EmbeddingProvider>,
+    store: FixtureStore,
+    provider: String,
+    model: String,
+}
+
+#[async_trait]
+impl EmbeddingProvider for FixtureEmbedder {
+    // Recorded per batch, so replay expects the same batching
+    // (`EMBEDDING_BATCH_SIZE`) as the recording
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError> {
+        let request = json!({
+            "method": "embed",
+            "provider": self.provider,
+            "model": self.model,
+            "task": format!("{:?}", task),
+            "dimensions": dimensions,
+            "texts": texts,
+        });
+        let path = self.store.path("embed", &request);
+
+        if self.store.mode == FixtureMode::Replay {
+            let recorded: RecordedEmbeddings = self.store.load(&path).await?;
+            return Ok(Embeddings {
+                vectors: recorded.vectors,
+                model: recorded.model,
+                usage: TokenUsage {
+                    prompt_tokens: recorded.prompt_tokens,
+                    completion_tokens: 0,
+                },
+            });
+        }
+
+        let embeddings = self.inner.embed_batch(texts, task, dimensions).await?;
+        let recorded = RecordedEmbeddings {
+            vectors: embeddings.vectors.clone(),
+            model: embeddings.model.clone(),
+            prompt_tokens: embeddings.usage.prompt_tokens,
+        };
+        self.store.save(&path, request, recorded).await;
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Answers like a provider would while recording; never called in replay
+    struct Live {
+        calls: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Live {
+        async fn generate_text(
+            &self,
+            prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(Generation {
+                text: format!("live reply to {}", prompt),
+                model: "gemini-1.5-pro-002".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: 12,
+                    completion_tokens: 7,
+                },
+            })
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            _schema: Option<&Value>,
+            params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            self.generate_text(prompt, params).await
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for Live {
+        async fn embed_batch(
+            &self,
+            texts: &[String],
+            _task: EmbeddingTask,
+            _dimensions: Option<usize>,
+        ) -> Result<Embeddings, LlmError> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(Embeddings {
+                vectors: texts.iter().map(|t| vec![t.len() as f32, 0.5]).collect(),
+                model: "text-embedding-004".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: 3,
+                    completion_tokens: 0,
+                },
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recorded_calls_replay_offline() {
+        let dir = std::env::temp_dir().join(format!("fixtures-{}", uuid::Uuid::new_v4()));
+        let store = |mode| {
+            FixtureStore::new(&FixtureConfig {
+                mode,
+                dir: dir.display().to_string(),
+            })
+            .unwrap()
+        };
+        let live = Arc::new(Live {
+            calls: Mutex::new(0),
+        });
+        let params = GenerationParams::default();
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let schema = json!({"type": "object"});
+
+        let recorder = store(FixtureMode::Record);
+        let llm = recorder.wrap_llm(live.clone(), "gemini".into(), "gemini-1.5-pro".into());
+        let embedder =
+            recorder.wrap_embedder(live.clone(), "gemini".into(), "text-embedding-004".into());
+        let recorded = llm
+            .generate_json("Extract", Some(&schema), &params)
+            .await
+            .unwrap();
+        embedder
+            .embed_batch(&texts, EmbeddingTask::RetrievalDocument, None)
+            .await
+            .unwrap();
+        assert_eq!(*live.calls.lock().unwrap(), 2);
+
+        let player = store(FixtureMode::Replay);
+        let llm = player.wrap_llm(live.clone(), "gemini".into(), "gemini-1.5-pro".into());
+        let embedder =
+            player.wrap_embedder(live.clone(), "gemini".into(), "text-embedding-004".into());
+        let replayed = llm
+            .generate_json("Extract", Some(&schema), &params)
+            .await
+            .unwrap();
+        assert_eq!(replayed.text, recorded.text);
+        assert_eq!(replayed.model, "gemini-1.5-pro-002");
+        assert_eq!(replayed.usage, recorded.usage);
+        let embeddings = embedder
+            .embed_batch(&texts, EmbeddingTask::RetrievalDocument, None)
+            .await
+            .unwrap();
+        assert_eq!(embeddings.vectors, vec![vec![5.0, 0.5], vec![6.0, 0.5]]);
+
+        let unrecorded = llm.generate_text("Extract", &params).await;
+        assert!(matches!(unrecorded, Err(LlmError::MissingFixture { .. })));
+        assert_eq!(*live.calls.lock().unwrap(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
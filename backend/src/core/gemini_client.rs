@@ -1,18 +1,38 @@
-use crate::core::config::Config;
-use reqwest::Client;
+use crate::core::chunker::estimate_tokens;
+use crate::core::config::{Config, GeminiConfig, GenerationParams, Secret};
+use crate::core::key_pool::KeyPool;
+use crate::core::llm_error::{LlmError, SafetyRating};
+use crate::core::resilience::ResilientClient;
+use crate::core::streaming::SseDecoder;
+use crate::core::traits::{
+    EmbeddingProvider, EmbeddingTask, Embeddings, Generation, GenerationChunk, GenerationStream,
+    LlmProvider, TokenUsage,
+};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use reqwest::RequestBuilder;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct GeminiClient {
-    client: Client,
-    api_key: String,
+    http: ResilientClient,
+    keys: Arc<KeyPool>,
     base_url: String,
+    models: GeminiConfig,
     mock_mode: bool,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct GenerateContentRequest {
     contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<SystemInstruction>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    safety_settings: Vec<SafetySetting>,
     generation_config: GenerationConfig,
 }
 
@@ -22,26 +42,90 @@ struct Content {
     role: String,
 }
 
+#[derive(Serialize)]
+struct SystemInstruction {
+    parts: Vec<Part>,
+}
+
 #[derive(Serialize)]
 struct Part {
     text: String,
 }
 
 #[derive(Serialize)]
+struct SafetySetting {
+    category: &'static str,
+    threshold: String,
+}
+
+// Categories a configured safety threshold is applied to
+const HARM_CATEGORIES: &[&str] = &[
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
     response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct GenerateContentResponse {
     candidates: Option<Vec<Candidate>>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct Candidate {
     content: Option<ContentResponse>,
+    finish_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+// finishReason values that mean the output was withheld by a content filter
+const BLOCKED_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
 #[derive(Deserialize, Debug)]
 struct ContentResponse {
     parts: Option<Vec<PartResponse>>,
@@ -53,198 +137,660 @@ struct PartResponse {
 }
 
 impl GeminiClient {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(config: &Config, http: ResilientClient) -> Self {
         Self {
-            client: Client::new(),
-            api_key: config.gemini_api_key.clone(),
+            http,
+            keys: Arc::new(KeyPool::new(
+                config.gemini_api_keys.clone(),
+                Duration::from_secs(config.resilience.key_quarantine_secs),
+            )),
             base_url: "https://generativelanguage.googleapis.com/v1beta/models".to_string(),
+            models: config.providers.gemini.clone(),
             mock_mode: config.mock_gemini,
         }
     }
 
-    pub async fn generate_text(
+    async fn generate_content(
         &self,
-        prompt: &str,
-    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!(
-            "{}/gemini-1.5-pro:generateContent?key={}",
-            self.base_url, self.api_key
-        );
+        prompt: String,
+        params: &GenerationParams,
+        response_mime_type: Option<String>,
+        response_schema: Option<Value>,
+    ) -> Result<Generation, LlmError> {
+        let model = params.model.as_deref().unwrap_or(&self.models.chat_model);
+        let url = format!("{}/{}:generateContent", self.base_url, model);
 
-        let request_body = GenerateContentRequest {
-            contents: vec![Content {
-                role: "user".to_string(),
-                parts: vec![Part {
-                    text: prompt.to_string(),
-                }],
-            }],
-            generation_config: GenerationConfig {
-                response_mime_type: None,
-            },
-        };
+        let request_body = build_request(prompt, params, response_mime_type, response_schema);
 
+        let tokens = estimate_tokens(&request_body.contents[0].parts[0].text) as u32;
         let res = self
-            .client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+            .http
+            .send_with_key(&self.keys, tokens, |client, key| {
+                authorized(client.post(&url), key).json(&request_body)
+            })
+            .await?;
+
+        let body = res.text().await.map_err(|e| LlmError::Network {
+            message: e.to_string(),
+        })?;
+        let response_body: GenerateContentResponse =
+            serde_json::from_str(&body).map_err(|e| LlmError::malformed(e, body.clone()))?;
+
+        let usage = response_body.usage_metadata.as_ref().map(|u| TokenUsage {
+            prompt_tokens: u.prompt_token_count,
+            completion_tokens: u.candidates_token_count,
+        });
+        let text = first_text(response_body, body)?;
+        Ok(Generation {
+            usage: usage.unwrap_or_else(|| TokenUsage {
+                prompt_tokens: tokens,
+                completion_tokens: estimate_tokens(&text) as u32,
+            }),
+            text,
+            model: model.to_string(),
+        })
+    }
 
-        if !res.status().is_success() {
-            let error_text = res.text().await.unwrap_or_default();
-            return Err(format!("Gemini API Error: {}", error_text).into());
-        }
+    // `streamGenerateContent` with `alt=sse`: every event is a partial
+    // GenerateContentResponse. Usage is reported on the last piece.
+    async fn stream_content(
+        &self,
+        prompt: String,
+        params: &GenerationParams,
+        response_mime_type: Option<String>,
+        response_schema: Option<Value>,
+    ) -> Result<GenerationStream, LlmError> {
+        let model = params.model.as_deref().unwrap_or(&self.models.chat_model);
+        let url = format!("{}/{}:streamGenerateContent?alt=sse", self.base_url, model);
 
-        let response_body: GenerateContentResponse = res
-            .json()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-
-        if let Some(candidates) = response_body.candidates {
-            if let Some(first) = candidates.first() {
-                if let Some(content) = &first.content {
-                    if let Some(parts) = &content.parts {
-                        if let Some(first_part) = parts.first() {
-                            return Ok(first_part.text.clone());
+        let request_body = build_request(prompt, params, response_mime_type, response_schema);
+
+        let tokens = estimate_tokens(&request_body.contents[0].parts[0].text) as u32;
+        let res = self
+            .http
+            .send_with_key(&self.keys, tokens, |client, key| {
+                authorized(client.post(&url), key).json(&request_body)
+            })
+            .await?;
+
+        let state = Some((res, SseDecoder::default(), String::new(), None));
+        let chunks = stream::unfold(state, move |state| async move {
+            let (mut res, mut decoder, mut text, mut usage) = state?;
+            match res.chunk().await {
+                Ok(Some(bytes)) => {
+                    let mut pieces = Vec::new();
+                    for event in decoder.push(&bytes) {
+                        match stream_piece(event) {
+                            Ok((piece, event_usage)) => {
+                                usage = event_usage.or(usage);
+                                text.push_str(&piece);
+                                if !piece.is_empty() {
+                                    pieces.push(Ok(GenerationChunk {
+                                        text: piece,
+                                        usage: None,
+                                    }));
+                                }
+                            }
+                            Err(e) => {
+                                pieces.push(Err(e));
+                                return Some((pieces, None));
+                            }
                         }
                     }
+                    Some((pieces, Some((res, decoder, text, usage))))
+                }
+                Ok(None) => {
+                    let usage = usage.unwrap_or_else(|| TokenUsage {
+                        prompt_tokens: tokens,
+                        completion_tokens: estimate_tokens(&text) as u32,
+                    });
+                    let last = GenerationChunk {
+                        text: String::new(),
+                        usage: Some(usage),
+                    };
+                    Some((vec![Ok(last)], None))
+                }
+                Err(e) => {
+                    let error = LlmError::Network {
+                        message: e.to_string(),
+                    };
+                    Some((vec![Err(error)], None))
                 }
             }
+        })
+        .flat_map(stream::iter)
+        .boxed();
+
+        Ok(GenerationStream {
+            model: model.to_string(),
+            chunks,
+        })
+    }
+}
+
+// The key goes in a header rather than `?key=` so it never appears in
+// logged URLs or error messages
+fn authorized(request: RequestBuilder, key: &Secret) -> RequestBuilder {
+    request.header("x-goog-api-key", key.expose())
+}
+
+fn build_request(
+    prompt: String,
+    params: &GenerationParams,
+    response_mime_type: Option<String>,
+    response_schema: Option<Value>,
+) -> GenerateContentRequest {
+    GenerateContentRequest {
+        contents: vec![Content {
+            role: "user".to_string(),
+            parts: vec![Part { text: prompt }],
+        }],
+        system_instruction: params
+            .system_instruction
+            .as_ref()
+            .map(|text| SystemInstruction {
+                parts: vec![Part { text: text.clone() }],
+            }),
+        safety_settings: params
+            .safety_threshold
+            .as_ref()
+            .map(|threshold| {
+                HARM_CATEGORIES
+                    .iter()
+                    .map(|category| SafetySetting {
+                        category,
+                        threshold: threshold.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        generation_config: GenerationConfig {
+            response_mime_type,
+            response_schema,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_output_tokens: params.max_output_tokens,
+            stop_sequences: params.stop_sequences.clone(),
+        },
+    }
+}
+
+// Model name recorded for mock-mode calls, which cost nothing
+const MOCK_MODEL: &str = "mock";
+
+fn mock_generation(prompt: &str, text: &str) -> Generation {
+    Generation {
+        text: text.to_string(),
+        model: MOCK_MODEL.to_string(),
+        usage: TokenUsage {
+            prompt_tokens: estimate_tokens(prompt) as u32,
+            completion_tokens: estimate_tokens(text) as u32,
+        },
+    }
+}
+
+// The error for a prompt or candidate withheld by the safety filters
+fn blocked(response: &GenerateContentResponse, body: &str) -> Option<LlmError> {
+    if let Some(feedback) = &response.prompt_feedback {
+        if let Some(reason) = &feedback.block_reason {
+            return Some(LlmError::Blocked {
+                reason: reason.clone(),
+                safety_ratings: feedback.safety_ratings.clone(),
+                body: body.to_string(),
+            });
         }
+    }
+    let candidate = response.candidates.as_ref()?.first()?;
+    let reason = candidate.finish_reason.as_ref()?;
+    BLOCKED_FINISH_REASONS
+        .contains(&reason.as_str())
+        .then(|| LlmError::Blocked {
+            reason: reason.clone(),
+            safety_ratings: candidate.safety_ratings.clone(),
+            body: body.to_string(),
+        })
+}
 
-        Err("No content generated".into())
+// The text of the first candidate, or why there is none
+fn first_text(response: GenerateContentResponse, body: String) -> Result<String, LlmError> {
+    if let Some(error) = blocked(&response, &body) {
+        return Err(error);
+    }
+
+    let Some(candidate) = response.candidates.and_then(|c| c.into_iter().next()) else {
+        return Err(LlmError::Incomplete {
+            finish_reason: None,
+            body,
+        });
+    };
+
+    let text = candidate
+        .content
+        .and_then(|c| c.parts)
+        .and_then(|parts| parts.into_iter().next())
+        .map(|part| part.text);
+    match text {
+        Some(text) => Ok(text),
+        None => {
+            eprintln!("Gemini Generation Failed. Raw Response: {}", body);
+            Err(LlmError::Incomplete {
+                finish_reason: candidate.finish_reason,
+                body,
+            })
+        }
+    }
+}
+
+// Text and usage of one streamed event. Events may carry no text (e.g. the
+// final one with only the finish reason).
+fn stream_piece(event: String) -> Result<(String, Option<TokenUsage>), LlmError> {
+    let response: GenerateContentResponse =
+        serde_json::from_str(&event).map_err(|e| LlmError::malformed(e, event.clone()))?;
+    if let Some(error) = blocked(&response, &event) {
+        return Err(error);
+    }
+    let usage = response.usage_metadata.as_ref().map(|u| TokenUsage {
+        prompt_tokens: u.prompt_token_count,
+        completion_tokens: u.candidates_token_count,
+    });
+    let text = response
+        .candidates
+        .into_iter()
+        .flatten()
+        .next()
+        .and_then(|c| c.content)
+        .and_then(|c| c.parts)
+        .into_iter()
+        .flatten()
+        .map(|part| part.text)
+        .collect();
+    Ok((text, usage))
+}
+
+// Reply streamed in mock mode, shaped like a generated exam (streaming is
+// only used for exams)
+const MOCK_EXAM: &str = r#"{"passages": [
+    {"kind": "transcript", "label": "Conversation 1", "text": "A: Is the library open? B: Until six."}
+], "questions": [
+    {"type": "sentence_completion", "stem": "Mock ___ 1", "options": ["A", "B", "C", "D"], "answer": "A", "explanation": "Mock explanation"},
+    {"type": "listening", "stem": "Mock Question 2", "options": ["A", "B", "C", "D"], "answer": "A", "explanation": null, "passage": 0}
+]}"#;
+
+#[async_trait]
+impl LlmProvider for GeminiClient {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        if self.mock_mode {
+            println!("Mock Mode: Returning fake text response.");
+            return Ok(mock_generation(prompt, "Mock response"));
+        }
+
+        self.generate_content(prompt.to_string(), params, None, None)
+            .await
     }
 
-    pub async fn generate_json(
+    async fn generate_json(
         &self,
         prompt: &str,
-    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
         if self.mock_mode {
             println!("Mock Mode: Returning fake JSON response.");
-            return Ok(r#"{
+            // Answer-key verification always picks the first choice
+            let verifying = schema.is_some_and(|s| s["properties"].get("also_correct").is_some());
+            if verifying {
+                return Ok(mock_generation(
+                    prompt,
+                    r#"{"reasoning": "Mock reasoning", "answer": "A", "also_correct": []}"#,
+                ));
+            }
+            return Ok(mock_generation(
+                prompt,
+                r#"{
+                "passages": [
+                    {"kind": "reading", "label": "Passage 1", "text": "Mock passage."}
+                ],
                 "questions": [
                     {
                         "topic": "reading",
                         "difficulty": "medium",
-                        "content": {"question": "Mock Question", "options": ["A", "B", "C", "D"]},
+                        "content": {
+                            "type": "reading_comprehension",
+                            "stem": "Mock Question",
+                            "options": ["A", "B", "C", "D"],
+                            "answer": "A",
+                            "passage": 0
+                        },
                         "text_for_embedding": "Mock Question Text for Embedding"
                     }
                 ]
-            }"#
-            .to_string());
+            }"#,
+            ));
         }
 
-        let url = format!(
-            "{}/gemini-1.5-pro:generateContent?key={}",
-            self.base_url, self.api_key
-        );
-
-        let request_body = GenerateContentRequest {
-            contents: vec![Content {
-                role: "user".to_string(),
-                parts: vec![Part {
-                    text: format!("{} \n Respond in JSON format.", prompt),
-                }],
-            }],
-            generation_config: GenerationConfig {
-                response_mime_type: Some("application/json".to_string()),
-            },
-        };
-
-        let res = self
-            .client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        self.generate_content(
+            format!("{} \n Respond in JSON format.", prompt),
+            params,
+            Some("application/json".to_string()),
+            schema.map(to_gemini_schema),
+        )
+        .await
+    }
 
-        // ... (Similar parsing logic, concise for MVP) ...
-        let response_body: GenerateContentResponse = res
-            .json()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-
-        if let Some(candidates) = &response_body.candidates {
-            if let Some(first) = candidates.first() {
-                if let Some(content) = &first.content {
-                    if let Some(parts) = &content.parts {
-                        if let Some(first_part) = parts.first() {
-                            return Ok(first_part.text.clone());
-                        }
-                    }
-                }
-            }
+    async fn stream_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<GenerationStream, LlmError> {
+        if self.mock_mode {
+            println!("Mock Mode: Streaming fake JSON response.");
+            let generation = mock_generation(prompt, MOCK_EXAM);
+            let mut pieces: Vec<Result<GenerationChunk, LlmError>> = MOCK_EXAM
+                .as_bytes()
+                .chunks(40)
+                .map(|piece| {
+                    Ok(GenerationChunk {
+                        text: String::from_utf8_lossy(piece).into_owned(),
+                        usage: None,
+                    })
+                })
+                .collect();
+            pieces.push(Ok(GenerationChunk {
+                text: String::new(),
+                usage: Some(generation.usage),
+            }));
+            return Ok(GenerationStream {
+                model: generation.model,
+                chunks: stream::iter(pieces).boxed(),
+            });
         }
 
-        eprintln!(
-            "Gemini JSON Generation Failed. Raw Response: {:?}",
-            response_body
-        );
-        Err("No JSON content generated".into())
+        self.stream_content(
+            format!("{} \n Respond in JSON format.", prompt),
+            params,
+            Some("application/json".to_string()),
+            schema.map(to_gemini_schema),
+        )
+        .await
     }
+}
 
-    pub async fn generate_embedding(
+#[async_trait]
+impl EmbeddingProvider for GeminiClient {
+    async fn embed_batch(
         &self,
-        text: &str,
-    ) -> Result<Vec<f32>, Box<dyn std::error::Error + Send + Sync>> {
+        texts: &[String],
+        task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError> {
+        let estimated_tokens: usize = texts.iter().map(|t| estimate_tokens(t)).sum();
         if self.mock_mode {
-            println!("Mock Mode: Returning fake Embedding.");
-            return Ok(vec![0.1; 768]);
+            println!("Mock Mode: Returning {} fake Embeddings.", texts.len());
+            return Ok(Embeddings {
+                vectors: vec![vec![0.1; dimensions.unwrap_or(768)]; texts.len()],
+                model: MOCK_MODEL.to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: estimated_tokens as u32,
+                    completion_tokens: 0,
+                },
+            });
         }
 
         let url = format!(
-            "{}/text-embedding-004:embedContent?key={}",
-            self.base_url, self.api_key
+            "{}/{}:batchEmbedContents",
+            self.base_url, self.models.embedding_model
         );
 
-        let request_body = EmbedContentRequest {
-            content: Content {
-                role: "user".to_string(),
-                parts: vec![Part {
-                    text: text.to_string(),
-                }],
-            },
+        let task_type = match task {
+            EmbeddingTask::RetrievalDocument => "RETRIEVAL_DOCUMENT",
+            EmbeddingTask::RetrievalQuery => "RETRIEVAL_QUERY",
+        };
+        let request_body = BatchEmbedContentsRequest {
+            requests: texts
+                .iter()
+                .map(|text| EmbedContentRequest {
+                    model: format!("models/{}", self.models.embedding_model),
+                    content: Content {
+                        role: "user".to_string(),
+                        parts: vec![Part { text: text.clone() }],
+                    },
+                    task_type,
+                    output_dimensionality: dimensions,
+                })
+                .collect(),
         };
 
         let res = self
-            .client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-
-        if !res.status().is_success() {
-            let error_text = res.text().await.unwrap_or_default();
-            return Err(format!("Gemini API Error (Embedding): {}", error_text).into());
+            .http
+            .send_with_key(&self.keys, estimated_tokens as u32, |client, key| {
+                authorized(client.post(&url), key).json(&request_body)
+            })
+            .await?;
+
+        let body = res.text().await.map_err(|e| LlmError::Network {
+            message: e.to_string(),
+        })?;
+        let response_body: BatchEmbedContentsResponse =
+            serde_json::from_str(&body).map_err(|e| LlmError::malformed(e, body.clone()))?;
+
+        if response_body.embeddings.len() != texts.len() {
+            return Err(LlmError::malformed(
+                format!(
+                    "Expected {} embeddings, got {}",
+                    texts.len(),
+                    response_body.embeddings.len()
+                ),
+                body,
+            ));
         }
+        // batchEmbedContents reports no usage; bill the estimate
+        Ok(Embeddings {
+            vectors: response_body
+                .embeddings
+                .into_iter()
+                .map(|embedding| embedding.values)
+                .collect(),
+            model: self.models.embedding_model.clone(),
+            usage: TokenUsage {
+                prompt_tokens: estimated_tokens as u32,
+                completion_tokens: 0,
+            },
+        })
+    }
+}
 
-        let response_body: EmbedContentResponse = res
-            .json()
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-
-        if let Some(embedding) = response_body.embedding {
-            return Ok(embedding.values);
+// Gemini's responseSchema is an OpenAPI subset: upper-case type names,
+// `nullable` instead of `"type": [.., "null"]`, and no JSON Schema keywords
+// such as `additionalProperties` or `$schema`
+fn to_gemini_schema(schema: &Value) -> Value {
+    const SUPPORTED_FORMATS: &[&str] = &["enum", "date-time", "int32", "int64", "float", "double"];
+    const SUPPORTED: &[&str] = &[
+        "type",
+        "format",
+        "description",
+        "nullable",
+        "enum",
+        "properties",
+        "required",
+        "items",
+    ];
+
+    match schema {
+        Value::Object(map) => {
+            let mut out = serde_json::Map::new();
+            for (key, value) in map {
+                if !SUPPORTED.contains(&key.as_str()) {
+                    continue;
+                }
+                let converted = match key.as_str() {
+                    "type" => {
+                        let types: Vec<&str> = match value {
+                            Value::Array(types) => {
+                                types.iter().filter_map(|t| t.as_str()).collect()
+                            }
+                            other => other.as_str().into_iter().collect(),
+                        };
+                        if types.contains(&"null") {
+                            out.insert("nullable".to_string(), Value::Bool(true));
+                        }
+                        let primary = types.iter().find(|t| **t != "null").unwrap_or(&"string");
+                        Value::String(primary.to_uppercase())
+                    }
+                    "format"
+                        if !value
+                            .as_str()
+                            .is_some_and(|f| SUPPORTED_FORMATS.contains(&f)) =>
+                    {
+                        continue
+                    }
+                    "properties" => Value::Object(
+                        value
+                            .as_object()
+                            .map(|props| {
+                                props
+                                    .iter()
+                                    .map(|(name, prop)| (name.clone(), to_gemini_schema(prop)))
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                    ),
+                    "items" => to_gemini_schema(value),
+                    _ => value.clone(),
+                };
+                out.insert(key.clone(), converted);
+            }
+            Value::Object(out)
         }
-
-        Err("No embedding generated".into())
+        other => other.clone(),
     }
 }
 
 #[derive(Serialize)]
+struct BatchEmbedContentsRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct EmbedContentRequest {
+    model: String,
     content: Content,
+    task_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<usize>,
 }
 
 #[derive(Deserialize, Debug)]
-struct EmbedContentResponse {
-    embedding: Option<ContentEmbedding>,
+struct BatchEmbedContentsResponse {
+    #[serde(default)]
+    embeddings: Vec<ContentEmbedding>,
 }
 
 #[derive(Deserialize, Debug)]
 struct ContentEmbedding {
     values: Vec<f32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_safety_block_is_reported() {
+        let body = r#"{"candidates": [{"finishReason": "SAFETY", "safetyRatings": [
+            {"category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH", "blocked": true},
+            {"category": "HARM_CATEGORY_HATE_SPEECH", "probability": "NEGLIGIBLE"}
+        ]}]}"#;
+        let response: GenerateContentResponse = serde_json::from_str(body).unwrap();
+
+        let error = first_text(response, body.to_string()).unwrap_err();
+        assert!(!error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            "Blocked by provider (SAFETY): HARM_CATEGORY_HARASSMENT"
+        );
+    }
+
+    #[test]
+    fn test_stream_events_carry_text_usage_and_blocks() {
+        let body = concat!(
+            "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"{\\\"questions\\\": [\"}]}}]}\r\n\r\n",
+            "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"]}\"}]}, \"finishReason\": \"STOP\"}],",
+            " \"usageMetadata\": {\"promptTokenCount\": 9, \"candidatesTokenCount\": 4}}\r\n\r\n",
+            "data: {\"candidates\": [{\"finishReason\": \"SAFETY\"}]}\r\n\r\n"
+        );
+        let mut decoder = SseDecoder::default();
+        let events = decoder.push(body.as_bytes());
+        assert_eq!(events.len(), 3);
+
+        let pieces: Vec<_> = events.into_iter().map(stream_piece).collect();
+        let (first, _) = pieces[0].as_ref().unwrap();
+        let (second, usage) = pieces[1].as_ref().unwrap();
+        assert_eq!(format!("{}{}", first, second), "{\"questions\": []}");
+        assert_eq!(
+            *usage,
+            Some(TokenUsage {
+                prompt_tokens: 9,
+                completion_tokens: 4
+            })
+        );
+        assert!(matches!(pieces[2], Err(LlmError::Blocked { .. })));
+    }
+
+    #[test]
+    fn test_json_schema_is_converted_for_gemini() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "questions": { "type": "array", "items": { "type": "string", "enum": ["a"] } },
+                "score": { "type": ["integer", "null"], "format": "uint32", "minimum": 0 }
+            },
+            "required": ["questions"]
+        });
+
+        assert_eq!(
+            to_gemini_schema(&schema),
+            json!({
+                "type": "OBJECT",
+                "properties": {
+                    "questions": { "type": "ARRAY", "items": { "type": "STRING", "enum": ["a"] } },
+                    "score": { "type": "INTEGER", "nullable": true }
+                },
+                "required": ["questions"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_generation_params_are_sent() {
+        let params = GenerationParams {
+            temperature: Some(0.25),
+            max_output_tokens: Some(2048),
+            stop_sequences: vec!["END".to_string()],
+            system_instruction: Some("You write CU-TEP questions.".to_string()),
+            safety_threshold: Some("BLOCK_ONLY_HIGH".to_string()),
+            ..GenerationParams::default()
+        };
+        let request = build_request("Prompt".to_string(), &params, None, None);
+        let body = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(
+            body["generationConfig"],
+            json!({ "temperature": 0.25, "maxOutputTokens": 2048, "stopSequences": ["END"] })
+        );
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "You write CU-TEP questions."
+        );
+        assert_eq!(body["safetySettings"].as_array().unwrap().len(), 4);
+        assert_eq!(body["safetySettings"][0]["threshold"], "BLOCK_ONLY_HIGH");
+    }
+}
@@ -0,0 +1,262 @@
+use crate::core::jobs;
+use crate::core::materials::{self, MaterialStatus};
+use crate::core::normalizer;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+// Accepted values of `raw_materials.source_type`
+pub const SOURCE_TYPES: &[&str] = &["web", "pdf", "docx", "text"];
+
+// A material as submitted by the collector
+#[derive(Debug, Clone)]
+pub struct NewMaterial {
+    pub url: String,
+    pub content: String,
+    pub source_type: String,
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IngestOutcome {
+    // Stored and queued for processing. `previous_revision_id` is set when the
+    // same URL was ingested before with different content.
+    Created {
+        id: Uuid,
+        revision: i32,
+        previous_revision_id: Option<Uuid>,
+    },
+    // Same content (or the same Idempotency-Key) was already ingested
+    Duplicate {
+        id: Uuid,
+    },
+    // Nothing is left once markup, scripts and page chrome are removed; not
+    // stored, since every such page would share one content hash
+    NoText,
+}
+
+// Store a material and enqueue its processing job, unless it is a duplicate.
+// Runs on the caller's connection so it can be part of a larger transaction;
+// it must be one, since the per-URL lock below is held until it ends. Keep it
+// short: ingests of the same URL in other transactions wait for it.
+pub async fn ingest_material(
+    conn: &mut PgConnection,
+    material: &NewMaterial,
+    max_attempts: i32,
+) -> Result<IngestOutcome, sqlx::Error> {
+    // 1. Replayed request
+    if let Some(key) = &material.idempotency_key {
+        if let Some(id) = find_by_idempotency_key(&mut *conn, key).await? {
+            return Ok(IngestOutcome::Duplicate { id });
+        }
+    }
+
+    // 2. Unchanged content. Hashing the cleaned text means re-scrapes that only
+    // differ in page chrome (ads, nav, scripts) are still duplicates.
+    let normalized = normalizer::normalize(&material.content);
+    if normalized.text.trim().is_empty() {
+        return Ok(IngestOutcome::NoText);
+    }
+    let hash = content_hash(&normalized.text);
+    if let Some(id) = find_by_hash(&mut *conn, &hash).await? {
+        return Ok(IngestOutcome::Duplicate { id });
+    }
+
+    // 3. New content, possibly a new revision of a known URL. Concurrent
+    // ingests of the same URL would read the same latest revision, so they
+    // take turns until the transaction ends.
+    sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtextextended($1, 0))",
+        material.url
+    )
+    .execute(&mut *conn)
+    .await?;
+    let previous = sqlx::query!(
+        "SELECT id, revision FROM raw_materials WHERE url = $1 ORDER BY revision DESC, created_at DESC LIMIT 1",
+        material.url
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let (revision, previous_revision_id) = match previous {
+        Some(prev) => (prev.revision + 1, Some(prev.id)),
+        None => (1, None),
+    };
+
+    // A concurrent request may have inserted the same hash or key since the
+    // lookups above; the unique indexes turn that into a no-op insert.
+    let inserted = sqlx::query!(
+        "INSERT INTO raw_materials (url, content, cleaned_content, content_format, source_type, content_hash, idempotency_key, revision, previous_revision_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+         ON CONFLICT DO NOTHING \
+         RETURNING id",
+        material.url,
+        material.content,
+        normalized.text,
+        normalized.format.as_str(),
+        material.source_type,
+        hash,
+        material.idempotency_key,
+        revision,
+        previous_revision_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let id = match inserted {
+        Some(record) => record.id,
+        None => {
+            let existing = match &material.idempotency_key {
+                Some(key) => find_by_idempotency_key(&mut *conn, key).await?,
+                None => None,
+            };
+            let existing = match existing {
+                Some(id) => Some(id),
+                None => find_by_hash(&mut *conn, &hash).await?,
+            };
+            return existing
+                .map(|id| IngestOutcome::Duplicate { id })
+                .ok_or(sqlx::Error::RowNotFound);
+        }
+    };
+
+    materials::record_event(&mut *conn, id, MaterialStatus::Queued, None).await?;
+    jobs::enqueue(&mut *conn, jobs::KIND_PROCESS_MATERIAL, id, max_attempts).await?;
+
+    Ok(IngestOutcome::Created {
+        id,
+        revision,
+        previous_revision_id,
+    })
+}
+
+async fn find_by_idempotency_key(
+    conn: &mut PgConnection,
+    key: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT id FROM raw_materials WHERE idempotency_key = $1",
+        key
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn find_by_hash(conn: &mut PgConnection, hash: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT id FROM raw_materials WHERE content_hash = $1", hash)
+        .fetch_optional(conn)
+        .await
+}
+
+// SHA-256 of the content with whitespace runs collapsed and ends trimmed, so
+// re-scrapes that only differ in formatting hash the same
+pub fn content_hash(content: &str) -> String {
+    let normalized = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash_ignores_whitespace_differences() {
+        let a = content_hash("Reading  passage\n\nabout science. ");
+        let b = content_hash("  Reading passage about\tscience.");
+        assert_eq!(a, b);
+        assert_ne!(a, content_hash("Reading passage about art."));
+        assert_eq!(a.len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_material_without_text_is_not_stored() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = sqlx::PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let mut tx = pool.begin().await.unwrap();
+        let material = NewMaterial {
+            url: "http://test.com/script-only".to_string(),
+            content: "<html><script>track();</script><style>p {}</style></html>".to_string(),
+            source_type: "web".to_string(),
+            idempotency_key: None,
+        };
+        let outcome = ingest_material(&mut tx, &material, 3).await.unwrap();
+        assert_eq!(outcome, IngestOutcome::NoText);
+        let stored = sqlx::query_scalar!(
+            r#"SELECT count(*) AS "count!" FROM raw_materials WHERE url = $1"#,
+            material.url
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        assert_eq!(stored, 0);
+        tx.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_revisions_of_a_url_are_numbered_in_turn() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = sqlx::PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let url = format!("http://test.com/revisions-{}", Uuid::new_v4());
+        let material = |content: &str| NewMaterial {
+            url: url.clone(),
+            content: format!("{} {}", content, url),
+            source_type: "web".to_string(),
+            idempotency_key: None,
+        };
+        let revision = |outcome: IngestOutcome| match outcome {
+            IngestOutcome::Created { revision, .. } => revision,
+            other => panic!("not created: {:?}", other),
+        };
+
+        let mut tx = pool.begin().await.unwrap();
+        let first = ingest_material(&mut tx, &material("First version"), 3).await;
+        assert_eq!(revision(first.unwrap()), 1);
+        tx.commit().await.unwrap();
+
+        // The second ingest waits for the first one's transaction
+        let mut tx = pool.begin().await.unwrap();
+        let second = ingest_material(&mut tx, &material("Second version"), 3).await;
+        let concurrent = tokio::spawn({
+            let pool = pool.clone();
+            let third = material("Third version");
+            async move {
+                let mut tx = pool.begin().await.unwrap();
+                let outcome = ingest_material(&mut tx, &third, 3).await.unwrap();
+                tx.commit().await.unwrap();
+                outcome
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        tx.commit().await.unwrap();
+        assert_eq!(revision(second.unwrap()), 2);
+        assert_eq!(revision(concurrent.await.unwrap()), 3);
+
+        let ids = sqlx::query_scalar!("SELECT id FROM raw_materials WHERE url = $1", url)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "DELETE FROM material_events WHERE raw_material_id = ANY($1)",
+            &ids
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!("DELETE FROM jobs WHERE raw_material_id = ANY($1)", &ids)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM raw_materials WHERE url = $1", url)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use sqlx::{PgExecutor, PgPool};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Retry backoff: BACKOFF_BASE_SECS * 2^(attempts - 1), capped at BACKOFF_MAX_SECS
+const BACKOFF_BASE_SECS: f64 = 30.0;
+const BACKOFF_MAX_SECS: f64 = 3600.0;
+
+pub const KIND_PROCESS_MATERIAL: &str = "process_material";
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub raw_material_id: Uuid,
+    pub attempts: i32,
+    pub max_attempts: i32,
+}
+
+// Enqueue a job. Takes any executor so callers can enqueue inside the same
+// transaction that created the material.
+pub async fn enqueue<'e>(
+    executor: impl PgExecutor<'e>,
+    kind: &str,
+    raw_material_id: Uuid,
+    max_attempts: i32,
+) -> Result<Uuid, sqlx::Error> {
+    let record = sqlx::query!(
+        "INSERT INTO jobs (kind, raw_material_id, max_attempts) VALUES ($1, $2, $3) RETURNING id",
+        kind,
+        raw_material_id,
+        max_attempts
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.id)
+}
+
+// Claim the next runnable job: pending jobs whose run_at has passed, or running
+// jobs whose lease expired (the worker holding them crashed or was restarted).
+pub async fn claim(pool: &PgPool, lease: Duration) -> Result<Option<Job>, sqlx::Error> {
+    let job = sqlx::query_as!(
+        Job,
+        r#"
+        UPDATE jobs
+        SET status = 'running',
+            attempts = attempts + 1,
+            locked_until = NOW() + make_interval(secs => $1),
+            updated_at = NOW()
+        WHERE id = (
+            SELECT id FROM jobs
+            WHERE attempts < max_attempts
+              AND ((status = 'pending' AND run_at <= NOW())
+                OR (status = 'running' AND locked_until < NOW()))
+            ORDER BY run_at
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+        )
+        RETURNING id, kind, raw_material_id, attempts, max_attempts
+        "#,
+        lease.as_secs_f64()
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+// Extend the lease of a running job so long-running work is not reclaimed
+pub async fn heartbeat(pool: &PgPool, job_id: Uuid, lease: Duration) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE jobs SET locked_until = NOW() + make_interval(secs => $2), updated_at = NOW() WHERE id = $1 AND status = 'running'",
+        job_id,
+        lease.as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn complete(pool: &PgPool, job_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE jobs SET status = 'done', locked_until = NULL, last_error = NULL, updated_at = NOW() WHERE id = $1",
+        job_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Record a failed attempt. The job is rescheduled with exponential backoff, or
+// moved to the dead-letter state once it has used up its attempts (or right
+// away when the error is not `retryable`).
+// Returns true when the job was dead-lettered.
+pub async fn fail(
+    pool: &PgPool,
+    job: &Job,
+    error: &str,
+    retryable: bool,
+) -> Result<bool, sqlx::Error> {
+    if !retryable || job.attempts >= job.max_attempts {
+        sqlx::query!(
+            "UPDATE jobs SET status = 'dead', locked_until = NULL, last_error = $2, updated_at = NOW() WHERE id = $1",
+            job.id,
+            error
+        )
+        .execute(pool)
+        .await?;
+        return Ok(true);
+    }
+
+    sqlx::query!(
+        "UPDATE jobs SET status = 'pending', locked_until = NULL, last_error = $2, run_at = NOW() + make_interval(secs => $3), updated_at = NOW() WHERE id = $1",
+        job.id,
+        error,
+        backoff(job.attempts).as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(false)
+}
+
+// Dead-letter running jobs whose lease expired on their final attempt; claim()
+// will not pick them up again so they would otherwise stay 'running' forever.
+// Returns the reaped jobs.
+pub async fn reap_expired(pool: &PgPool) -> Result<Vec<Job>, sqlx::Error> {
+    sqlx::query_as!(
+        Job,
+        "UPDATE jobs SET status = 'dead', locked_until = NULL, last_error = COALESCE(last_error, 'Lease expired'), updated_at = NOW() \
+         WHERE status = 'running' AND locked_until < NOW() AND attempts >= max_attempts \
+         RETURNING id, kind, raw_material_id, attempts, max_attempts"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Enqueue jobs for unprocessed materials that have no job yet (e.g. rows
+// ingested before the queue existed, when processing ran in a bare tokio::spawn).
+pub async fn enqueue_orphaned_materials(
+    pool: &PgPool,
+    max_attempts: i32,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO jobs (kind, raw_material_id, max_attempts) \
+         SELECT $1, m.id, $2 FROM raw_materials m \
+         WHERE m.processed IS NOT TRUE \
+           AND NOT EXISTS (SELECT 1 FROM jobs j WHERE j.raw_material_id = m.id AND j.kind = $1)",
+        KIND_PROCESS_MATERIAL,
+        max_attempts
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16);
+    let secs = (BACKOFF_BASE_SECS * 2f64.powi(exponent)).min(BACKOFF_MAX_SECS);
+    Duration::from_secs_f64(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Row;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff(1), Duration::from_secs(30));
+        assert_eq!(backoff(2), Duration::from_secs(60));
+        assert_eq!(backoff(3), Duration::from_secs(120));
+        assert_eq!(backoff(20), Duration::from_secs(3600));
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_is_retried_then_dead_lettered() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let raw_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO raw_materials (id, url, content, source_type) VALUES ($1, $2, $3, $4)",
+            raw_id,
+            "http://test.com/jobs-test",
+            "Jobs Test Content",
+            "unit-test"
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert raw material");
+
+        let job_id = enqueue(&pool, KIND_PROCESS_MATERIAL, raw_id, 2)
+            .await
+            .expect("Failed to enqueue");
+
+        // First failure: back to pending, scheduled in the future
+        let mut job = Job {
+            id: job_id,
+            kind: KIND_PROCESS_MATERIAL.to_string(),
+            raw_material_id: raw_id,
+            attempts: 1,
+            max_attempts: 2,
+        };
+        let dead = fail(&pool, &job, "boom", true)
+            .await
+            .expect("Failed to fail job");
+        assert!(!dead);
+
+        let row = sqlx::query("SELECT status, run_at > NOW() FROM jobs WHERE id = $1")
+            .bind(job_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to fetch job");
+        assert_eq!(row.get::<String, _>(0), "pending");
+        assert!(row.get::<bool, _>(1), "Retry was not delayed");
+
+        // Final failure: dead-lettered
+        job.attempts = 2;
+        let dead = fail(&pool, &job, "boom again", true)
+            .await
+            .expect("Failed to fail job");
+        assert!(dead);
+
+        let row = sqlx::query("SELECT status, last_error FROM jobs WHERE id = $1")
+            .bind(job_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to fetch job");
+        assert_eq!(row.get::<String, _>(0), "dead");
+        assert_eq!(row.get::<String, _>(1), "boom again");
+
+        sqlx::query!("DELETE FROM jobs WHERE id = $1", job_id)
+            .execute(&pool)
+            .await
+            .ok();
+        sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+    }
+}
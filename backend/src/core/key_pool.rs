@@ -0,0 +1,112 @@
+use crate::core::config::Secret;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// API keys handed out round-robin. A key that runs into its quota is
+// quarantined and skipped until the quarantine expires.
+pub struct KeyPool {
+    keys: Vec<Secret>,
+    quarantined_until: Mutex<Vec<Option<Instant>>>,
+    next: AtomicUsize,
+    default_quarantine: Duration,
+}
+
+// A key handed out by the pool; pass it back to `quarantine` if it hits its quota
+pub struct PooledKey {
+    index: usize,
+    pub key: Secret,
+}
+
+impl KeyPool {
+    pub fn new(keys: Vec<Secret>, default_quarantine: Duration) -> Self {
+        Self {
+            quarantined_until: Mutex::new(vec![None; keys.len()]),
+            keys,
+            next: AtomicUsize::new(0),
+            default_quarantine,
+        }
+    }
+
+    // The next key that is not quarantined, or how long until one is
+    pub fn acquire(&self) -> Result<PooledKey, Duration> {
+        let now = Instant::now();
+        let quarantined_until = self.quarantined_until.lock().unwrap();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..self.keys.len() {
+            let index = (start + offset) % self.keys.len();
+            if quarantined_until[index].is_none_or(|until| until <= now) {
+                return Ok(PooledKey {
+                    index,
+                    key: self.keys[index].clone(),
+                });
+            }
+        }
+
+        Err(quarantined_until
+            .iter()
+            .flatten()
+            .map(|until| until.saturating_duration_since(now))
+            .min()
+            .unwrap_or_default())
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn has_available(&self) -> bool {
+        let now = Instant::now();
+        self.quarantined_until
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|until| until.is_none_or(|until| until <= now))
+    }
+
+    // Skip `key` for `retry_after`, or the default quarantine if unknown
+    pub fn quarantine(&self, key: &PooledKey, retry_after: Option<Duration>) {
+        let duration = retry_after.unwrap_or(self.default_quarantine);
+        let mut quarantined_until = self.quarantined_until.lock().unwrap();
+        quarantined_until[key.index] = Some(Instant::now() + duration);
+        eprintln!(
+            "API key #{} of {} hit its quota, quarantined for {}s",
+            key.index + 1,
+            self.keys.len(),
+            duration.as_secs()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_skips_quarantined_keys() {
+        let pool = KeyPool::new(
+            vec![Secret::new("a"), Secret::new("b"), Secret::new("c")],
+            Duration::from_secs(60),
+        );
+        let keys: Vec<String> = (0..4)
+            .map(|_| pool.acquire().unwrap().key.expose().to_string())
+            .collect();
+        assert_eq!(keys, ["a", "b", "c", "a"]);
+
+        let b = pool.acquire().unwrap();
+        assert_eq!(b.key.expose(), "b");
+        pool.quarantine(&b, None);
+        let keys: Vec<String> = (0..3)
+            .map(|_| pool.acquire().unwrap().key.expose().to_string())
+            .collect();
+        assert_eq!(keys, ["c", "a", "c"]);
+
+        for _ in 0..2 {
+            let key = pool.acquire().unwrap();
+            pool.quarantine(&key, Some(Duration::from_secs(5)));
+        }
+        let wait = pool.acquire().err().expect("every key is quarantined");
+        assert!(wait <= Duration::from_secs(5));
+    }
+}
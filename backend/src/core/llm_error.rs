@@ -0,0 +1,258 @@
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+// Why a call to a model provider failed. Callers branch on the variant to
+// decide whether to retry, dead-letter or alert.
+#[derive(Debug, Clone)]
+pub enum LlmError {
+    // The provider could not be reached (connect error, timeout)
+    Network {
+        message: String,
+    },
+    // Quota or rate limit exhausted (HTTP 429 / RESOURCE_EXHAUSTED)
+    RateLimited {
+        code: Option<String>,
+        message: String,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    // Any other non-success HTTP response
+    Http {
+        status: u16,
+        code: Option<String>,
+        message: String,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    // The prompt or response was blocked by the provider's safety filters
+    Blocked {
+        reason: String,
+        safety_ratings: Vec<SafetyRating>,
+        body: String,
+    },
+    // Generation ended without usable output (e.g. MAX_TOKENS, no candidates)
+    Incomplete {
+        finish_reason: Option<String>,
+        body: String,
+    },
+    // The response could not be decoded into what the caller expected
+    MalformedResponse {
+        message: String,
+        body: String,
+    },
+    // Calls are paused by the circuit breaker after repeated failures
+    Unavailable {
+        retry_in: Duration,
+    },
+    // Replay mode has no recorded response for the request
+    MissingFixture {
+        path: String,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+// Error body shared closely enough by Gemini ({"error": {code, message,
+// status}}) and OpenAI-compatible servers ({"error": {message, type, code}})
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    code: Option<serde_json::Value>,
+}
+
+impl LlmError {
+    // Build the error for a non-success HTTP response
+    pub fn from_response(status: u16, body: String, retry_after: Option<Duration>) -> Self {
+        let detail = serde_json::from_str::<ErrorEnvelope>(&body)
+            .ok()
+            .map(|e| e.error);
+        let message = detail
+            .as_ref()
+            .and_then(|d| d.message.clone())
+            .unwrap_or_else(|| body.chars().take(500).collect());
+        let code = detail.and_then(|d| {
+            d.status.or(d.kind).or(d.code.and_then(|c| match c {
+                serde_json::Value::String(s) => Some(s),
+                _ => None,
+            }))
+        });
+
+        if status == 429 || code.as_deref() == Some("RESOURCE_EXHAUSTED") {
+            LlmError::RateLimited {
+                code,
+                message,
+                retry_after,
+                body,
+            }
+        } else {
+            LlmError::Http {
+                status,
+                code,
+                message,
+                retry_after,
+                body,
+            }
+        }
+    }
+
+    pub fn malformed(message: impl fmt::Display, body: impl Into<String>) -> Self {
+        LlmError::MalformedResponse {
+            message: message.to_string(),
+            body: body.into(),
+        }
+    }
+
+    // How long the provider asked us to wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmError::RateLimited { retry_after, .. } | LlmError::Http { retry_after, .. } => {
+                *retry_after
+            }
+            LlmError::Unavailable { retry_in } => Some(*retry_in),
+            _ => None,
+        }
+    }
+
+    // The provider's response body, kept for logs and alerts
+    pub fn raw_body(&self) -> Option<&str> {
+        match self {
+            LlmError::RateLimited { body, .. }
+            | LlmError::Http { body, .. }
+            | LlmError::Blocked { body, .. }
+            | LlmError::Incomplete { body, .. }
+            | LlmError::MalformedResponse { body, .. } => Some(body),
+            LlmError::Network { .. }
+            | LlmError::Unavailable { .. }
+            | LlmError::MissingFixture { .. } => None,
+        }
+    }
+
+    // Whether the same request may succeed if tried again later. Blocked
+    // content and client errors (bad request, auth) will not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::Network { .. }
+            | LlmError::RateLimited { .. }
+            | LlmError::Unavailable { .. }
+            | LlmError::Incomplete { .. }
+            | LlmError::MalformedResponse { .. } => true,
+            LlmError::Http { status, .. } => *status == 408 || *status >= 500,
+            LlmError::Blocked { .. } | LlmError::MissingFixture { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Network { message } => write!(f, "Network error: {}", message),
+            LlmError::RateLimited { code, message, .. } => write!(
+                f,
+                "Rate limited ({}): {}",
+                code.as_deref().unwrap_or("429"),
+                message
+            ),
+            LlmError::Http {
+                status,
+                code,
+                message,
+                ..
+            } => match code {
+                Some(code) => write!(f, "HTTP {} ({}): {}", status, code, message),
+                None => write!(f, "HTTP {}: {}", status, message),
+            },
+            LlmError::Blocked {
+                reason,
+                safety_ratings,
+                ..
+            } => {
+                let flagged: Vec<&str> = safety_ratings
+                    .iter()
+                    .filter(|r| r.blocked || r.probability == "HIGH" || r.probability == "MEDIUM")
+                    .map(|r| r.category.as_str())
+                    .collect();
+                if flagged.is_empty() {
+                    write!(f, "Blocked by provider ({})", reason)
+                } else {
+                    write!(
+                        f,
+                        "Blocked by provider ({}): {}",
+                        reason,
+                        flagged.join(", ")
+                    )
+                }
+            }
+            LlmError::Incomplete { finish_reason, .. } => write!(
+                f,
+                "No content generated (finish reason: {})",
+                finish_reason.as_deref().unwrap_or("none")
+            ),
+            LlmError::MalformedResponse { message, .. } => {
+                write!(f, "Malformed response: {}", message)
+            }
+            LlmError::Unavailable { retry_in } => write!(
+                f,
+                "Provider unavailable: circuit breaker open for another {}s",
+                retry_in.as_secs()
+            ),
+            LlmError::MissingFixture { path } => {
+                write!(f, "No recorded response for this request ({})", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_bodies_are_classified() {
+        let quota = LlmError::from_response(
+            429,
+            r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#.to_string(),
+            Some(Duration::from_secs(7)),
+        );
+        assert!(matches!(
+            &quota,
+            LlmError::RateLimited { code: Some(code), retry_after: Some(_), .. } if code == "RESOURCE_EXHAUSTED"
+        ));
+        assert!(quota.is_retryable());
+
+        let bad_key = LlmError::from_response(
+            401,
+            r#"{"error": {"message": "Incorrect API key", "type": "invalid_request_error", "code": "invalid_api_key"}}"#.to_string(),
+            None,
+        );
+        assert_eq!(
+            bad_key.to_string(),
+            "HTTP 401 (invalid_request_error): Incorrect API key"
+        );
+        assert!(!bad_key.is_retryable());
+
+        let outage = LlmError::from_response(503, "upstream connect error".to_string(), None);
+        assert_eq!(outage.to_string(), "HTTP 503: upstream connect error");
+        assert!(outage.is_retryable());
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+// Lifecycle of a raw material as it moves through the processing pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialStatus {
+    Queued,
+    Extracting,
+    Verifying,
+    Embedding,
+    Done,
+    Failed,
+}
+
+impl MaterialStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaterialStatus::Queued => "queued",
+            MaterialStatus::Extracting => "extracting",
+            MaterialStatus::Verifying => "verifying",
+            MaterialStatus::Embedding => "embedding",
+            MaterialStatus::Done => "done",
+            MaterialStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "queued" => Some(MaterialStatus::Queued),
+            "extracting" => Some(MaterialStatus::Extracting),
+            "verifying" => Some(MaterialStatus::Verifying),
+            "embedding" => Some(MaterialStatus::Embedding),
+            "done" => Some(MaterialStatus::Done),
+            "failed" => Some(MaterialStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MaterialSummary {
+    pub id: Uuid,
+    pub url: String,
+    pub source_type: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub question_count: i64,
+    pub quarantined_count: i64,
+    pub passage_count: i64,
+    // Questions whose answer key did not verify cleanly
+    pub flagged_count: i64,
+    // Questions whose verification call failed; checked again on the next run
+    pub unverified_count: i64,
+    pub embedding_count: i64,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MaterialEvent {
+    pub status: String,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Move a material to a new status and append it to the history.
+// `Done` also sets the legacy `processed` flag; `Queued` after a failed
+// attempt keeps the error so callers can see why it is being retried.
+pub async fn set_status(
+    pool: &PgPool,
+    material_id: Uuid,
+    status: MaterialStatus,
+    message: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE raw_materials
+        SET status = $2,
+            error = CASE WHEN $2 IN ('queued', 'failed') THEN COALESCE($3, error) WHEN $2 = 'done' THEN NULL ELSE error END,
+            processed = (processed IS TRUE OR $2 = 'done'),
+            processed_at = CASE WHEN $2 = 'done' THEN NOW() ELSE processed_at END,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+        material_id,
+        status.as_str(),
+        message
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_event(&mut *tx, material_id, status, message).await?;
+
+    tx.commit().await
+}
+
+// Append a history entry without touching the material row (used on insert,
+// where the row already carries its initial status)
+pub async fn record_event<'e>(
+    executor: impl PgExecutor<'e>,
+    material_id: Uuid,
+    status: MaterialStatus,
+    message: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO material_events (raw_material_id, status, message) VALUES ($1, $2, $3)",
+        material_id,
+        status.as_str(),
+        message
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_summary(
+    pool: &PgPool,
+    material_id: Uuid,
+) -> Result<Option<MaterialSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        MaterialSummary,
+        r#"
+        SELECT m.id, m.url, m.source_type, m.status, m.error,
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id) AS "question_count!",
+            (SELECT count(*) FROM quarantined_questions q WHERE q.raw_material_id = m.id) AS "quarantined_count!",
+            (SELECT count(*) FROM passages p WHERE p.raw_material_id = m.id) AS "passage_count!",
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id AND q.verification_status <> 'verified') AS "flagged_count!",
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id AND q.verification_status IS NULL AND q.verification_error IS NOT NULL) AS "unverified_count!",
+            (SELECT count(*) FROM embeddings e JOIN questions q ON e.question_id = q.id WHERE q.raw_material_id = m.id) AS "embedding_count!",
+            COALESCE((SELECT max(j.attempts) FROM jobs j WHERE j.raw_material_id = m.id), 0) AS "attempts!",
+            m.created_at, m.updated_at, m.processed_at
+        FROM raw_materials m
+        WHERE m.id = $1
+        "#,
+        material_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_summaries(
+    pool: &PgPool,
+    status: Option<MaterialStatus>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<MaterialSummary>, i64), sqlx::Error> {
+    let status = status.map(|s| s.as_str());
+
+    let items = sqlx::query_as!(
+        MaterialSummary,
+        r#"
+        SELECT m.id, m.url, m.source_type, m.status, m.error,
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id) AS "question_count!",
+            (SELECT count(*) FROM quarantined_questions q WHERE q.raw_material_id = m.id) AS "quarantined_count!",
+            (SELECT count(*) FROM passages p WHERE p.raw_material_id = m.id) AS "passage_count!",
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id AND q.verification_status <> 'verified') AS "flagged_count!",
+            (SELECT count(*) FROM questions q WHERE q.raw_material_id = m.id AND q.verification_status IS NULL AND q.verification_error IS NOT NULL) AS "unverified_count!",
+            (SELECT count(*) FROM embeddings e JOIN questions q ON e.question_id = q.id WHERE q.raw_material_id = m.id) AS "embedding_count!",
+            COALESCE((SELECT max(j.attempts) FROM jobs j WHERE j.raw_material_id = m.id), 0) AS "attempts!",
+            m.created_at, m.updated_at, m.processed_at
+        FROM raw_materials m
+        WHERE ($1::TEXT IS NULL OR m.status = $1)
+        ORDER BY m.created_at DESC, m.id
+        LIMIT $2 OFFSET $3
+        "#,
+        status,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM raw_materials WHERE ($1::TEXT IS NULL OR status = $1)"#,
+        status
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((items, total))
+}
+
+pub async fn get_history(
+    pool: &PgPool,
+    material_id: Uuid,
+) -> Result<Vec<MaterialEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        MaterialEvent,
+        "SELECT status, message, created_at FROM material_events WHERE raw_material_id = $1 ORDER BY created_at, id",
+        material_id
+    )
+    .fetch_all(pool)
+    .await
+}
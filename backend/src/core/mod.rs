@@ -5,3 +5,24 @@ pub mod traits;
 pub mod accessors;
 pub mod engines;
 pub mod education_manager;
+pub mod documents;
+pub mod ingestion;
+pub mod jobs;
+pub mod materials;
+pub mod normalizer;
+pub mod worker;
+pub mod chunker;
+pub mod providers;
+pub mod openai_client;
+pub mod resilience;
+pub mod llm_error;
+pub mod structured;
+pub mod key_pool;
+pub mod usage;
+pub mod cache;
+pub mod fixtures;
+pub mod prompts;
+pub mod streaming;
+pub mod questions;
+pub mod readability;
+pub mod verification;
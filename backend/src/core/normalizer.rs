@@ -0,0 +1,296 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+// Elements that never carry article text
+const SKIP_TAGS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "iframe", "svg", "canvas", "nav", "header",
+    "footer", "aside", "form", "button", "select", "input", "textarea",
+];
+
+// Elements that start a new paragraph in the extracted text
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "blockquote",
+    "pre",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "figure",
+    "figcaption",
+    "hr",
+];
+
+// class/id tokens that mark navigation, ads and other page chrome
+const BOILERPLATE_TOKENS: &[&str] = &[
+    "nav",
+    "navbar",
+    "menu",
+    "breadcrumb",
+    "breadcrumbs",
+    "sidebar",
+    "footer",
+    "header",
+    "masthead",
+    "ad",
+    "ads",
+    "advert",
+    "advertisement",
+    "sponsored",
+    "banner",
+    "cookie",
+    "cookies",
+    "consent",
+    "popup",
+    "modal",
+    "share",
+    "social",
+    "comments",
+    "related",
+    "newsletter",
+    "subscribe",
+];
+
+// Containers tried in order when looking for the main article
+const MAIN_SELECTORS: &[&str] = &[
+    "article",
+    "main",
+    "[role=main]",
+    "#content",
+    ".content",
+    ".post",
+    ".entry-content",
+];
+
+// Minimum text length for a main-content candidate to be trusted over <body>
+const MIN_MAIN_CONTENT_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFormat {
+    Html,
+    Text,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Html => "html",
+            ContentFormat::Text => "text",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NormalizedContent {
+    pub format: ContentFormat,
+    // Plain text with paragraphs separated by a blank line
+    pub text: String,
+}
+
+// Turn raw collector content into prompt-ready text. HTML is reduced to the
+// main article text; plain text only has its whitespace tidied.
+pub fn normalize(raw: &str) -> NormalizedContent {
+    if looks_like_html(raw) {
+        NormalizedContent {
+            format: ContentFormat::Html,
+            text: html_to_text(raw),
+        }
+    } else {
+        NormalizedContent {
+            format: ContentFormat::Text,
+            text: tidy_paragraphs(raw),
+        }
+    }
+}
+
+pub fn looks_like_html(raw: &str) -> bool {
+    let head: String = raw.trim_start().chars().take(2048).collect();
+    let head = head.to_lowercase();
+    [
+        "<!doctype html",
+        "<html",
+        "<body",
+        "<head",
+        "<div",
+        "<p>",
+        "<p ",
+        "<article",
+        "<br",
+    ]
+    .iter()
+    .any(|marker| head.contains(marker))
+}
+
+pub fn html_to_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+
+    let body_selector = Selector::parse("body").expect("valid selector");
+    let body = document
+        .select(&body_selector)
+        .next()
+        .unwrap_or_else(|| document.root_element());
+
+    for css in MAIN_SELECTORS {
+        let selector = Selector::parse(css).expect("valid selector");
+        let best = document
+            .select(&selector)
+            .filter(|el| !is_boilerplate(el))
+            .map(extract)
+            .max_by_key(|text| text.len());
+        if let Some(text) = best {
+            if text.len() >= MIN_MAIN_CONTENT_CHARS {
+                return text;
+            }
+        }
+    }
+
+    extract(body)
+}
+
+fn extract(element: ElementRef) -> String {
+    let mut out = String::new();
+    walk(element, &mut out);
+    tidy_paragraphs(&out)
+}
+
+fn walk(element: ElementRef, out: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => push_inline(out, text),
+            Node::Element(el) => {
+                let name = el.name();
+                if name == "br" {
+                    out.push('\n');
+                    continue;
+                }
+                let Some(child_el) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                if SKIP_TAGS.contains(&name) || is_boilerplate(&child_el) {
+                    continue;
+                }
+
+                let block = BLOCK_TAGS.contains(&name);
+                if block {
+                    out.push_str("\n\n");
+                }
+                if name == "li" {
+                    out.push_str("- ");
+                } else if name == "td" || name == "th" {
+                    out.push(' ');
+                }
+                walk(child_el, out);
+                if block {
+                    out.push_str("\n\n");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn push_inline(out: &mut String, text: &str) {
+    if text.trim().is_empty() {
+        if !text.is_empty() && !out.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !out.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+    out.push_str(&text.split_whitespace().collect::<Vec<_>>().join(" "));
+    if text.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn is_boilerplate(element: &ElementRef) -> bool {
+    let el = element.value();
+    if el.attr("role") == Some("navigation") || el.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    let attrs = [el.attr("class"), el.attr("id")];
+    attrs.iter().flatten().any(|value| {
+        value
+            .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+            .any(|token| BOILERPLATE_TOKENS.contains(&token.to_lowercase().as_str()))
+    })
+}
+
+// Collapse whitespace inside paragraphs and separate paragraphs by one blank
+// line. Single newlines are kept as line breaks within a paragraph.
+fn tidy_paragraphs(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    text.split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_is_reduced_to_article_text() {
+        let html = r#"<!DOCTYPE html>
+            <html><head><title>Practice</title><style>p { color: red }</style></head>
+            <body>
+              <nav><a href="/">Home</a> | <a href="/tests">Tests</a></nav>
+              <div class="ad-banner">Buy now!</div>
+              <article>
+                <h1>Reading Passage 1</h1>
+                <p>Artificial intelligence is changing how   students <b>learn</b>.</p>
+                <script>track();</script>
+                <p>Teachers now use adaptive tools to personalise practice, and many schools
+                   report better results in reading comprehension.</p>
+                <ul><li>Option A</li><li>Option B</li></ul>
+                <div class="share-buttons">Share on Facebook</div>
+              </article>
+              <footer>Copyright 2024</footer>
+            </body></html>"#;
+
+        let normalized = normalize(html);
+        assert_eq!(normalized.format, ContentFormat::Html);
+        assert_eq!(
+            normalized.text,
+            "Reading Passage 1\n\n\
+             Artificial intelligence is changing how students learn.\n\n\
+             Teachers now use adaptive tools to personalise practice, and many schools report better results in reading comprehension.\n\n\
+             - Option A\n\n\
+             - Option B"
+        );
+    }
+
+    #[test]
+    fn test_plain_text_keeps_paragraphs() {
+        let normalized = normalize("  First   paragraph\nsecond line.\r\n\r\n\n\nNext   one. ");
+        assert_eq!(normalized.format, ContentFormat::Text);
+        assert_eq!(
+            normalized.text,
+            "First paragraph\nsecond line.\n\nNext one."
+        );
+    }
+}
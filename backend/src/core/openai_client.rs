@@ -0,0 +1,353 @@
+use crate::core::chunker::estimate_tokens;
+use crate::core::config::{GenerationParams, OpenAiConfig};
+use crate::core::llm_error::LlmError;
+use crate::core::resilience::ResilientClient;
+use crate::core::traits::{
+    EmbeddingProvider, EmbeddingTask, Embeddings, Generation, LlmProvider, TokenUsage,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Client for the OpenAI wire format: /chat/completions and /embeddings under
+// a configurable base URL, so local model servers can stand in for Gemini
+#[derive(Clone)]
+pub struct OpenAiClient {
+    http: ResilientClient,
+    config: OpenAiConfig,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatChoice>,
+    usage: Option<Usage>,
+}
+
+// Token counts; embedding responses only carry `prompt_tokens`
+#[derive(Deserialize, Debug)]
+struct Usage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatChoice {
+    message: ChatChoiceMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatChoiceMessage {
+    content: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAiClient {
+    pub fn new(config: &OpenAiConfig, http: ResilientClient) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    async fn post<T: Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+        estimated_tokens: usize,
+    ) -> Result<String, LlmError> {
+        let url = self.url(path);
+        let res = self
+            .http
+            .send(estimated_tokens as u32, |client| {
+                let request = client.post(&url).json(body);
+                // Local servers usually run without a key
+                match &self.config.api_key {
+                    Some(key) => request.bearer_auth(key.expose()),
+                    None => request,
+                }
+            })
+            .await?;
+        res.text().await.map_err(|e| LlmError::Network {
+            message: e.to_string(),
+        })
+    }
+
+    // Safety thresholds have no equivalent in this format and are ignored
+    async fn chat(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+        response_format: Option<Value>,
+    ) -> Result<Generation, LlmError> {
+        let mut messages = Vec::with_capacity(2);
+        if let Some(system) = &params.system_instruction {
+            messages.push(ChatMessage {
+                role: "system",
+                content: system,
+            });
+        }
+        messages.push(ChatMessage {
+            role: "user",
+            content: prompt,
+        });
+
+        let model = params.model.as_deref().unwrap_or(&self.config.chat_model);
+        let request_body = ChatCompletionRequest {
+            model,
+            messages,
+            response_format,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_tokens: params.max_output_tokens,
+            stop: &params.stop_sequences,
+        };
+
+        let body = self
+            .post("chat/completions", &request_body, estimate_tokens(prompt))
+            .await?;
+        let response_body: ChatCompletionResponse =
+            serde_json::from_str(&body).map_err(|e| LlmError::malformed(e, body.clone()))?;
+
+        let usage = response_body.usage.map(|u| TokenUsage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+        });
+        let Some(choice) = response_body.choices.into_iter().next() else {
+            return Err(LlmError::Incomplete {
+                finish_reason: None,
+                body,
+            });
+        };
+        match (choice.message.content, choice.finish_reason) {
+            (_, Some(reason)) if reason == "content_filter" => Err(LlmError::Blocked {
+                reason,
+                safety_ratings: Vec::new(),
+                body,
+            }),
+            (Some(content), _) => Ok(Generation {
+                usage: usage.unwrap_or_else(|| TokenUsage {
+                    prompt_tokens: estimate_tokens(prompt) as u32,
+                    completion_tokens: estimate_tokens(&content) as u32,
+                }),
+                text: content,
+                model: model.to_string(),
+            }),
+            (None, finish_reason) => Err(LlmError::Incomplete {
+                finish_reason,
+                body,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiClient {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        self.chat(prompt, params, None).await
+    }
+
+    async fn generate_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let response_format = match schema {
+            Some(schema) => json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": schema }
+            }),
+            None => json!({ "type": "json_object" }),
+        };
+        self.chat(
+            &format!("{} \n Respond in JSON format.", prompt),
+            params,
+            Some(response_format),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiClient {
+    // The OpenAI format has no task type; the whole batch goes in one `input`
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        _task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError> {
+        let request_body = EmbeddingRequest {
+            model: &self.config.embedding_model,
+            input: texts,
+            dimensions,
+        };
+        let estimated_tokens: usize = texts.iter().map(|t| estimate_tokens(t)).sum();
+
+        let body = self
+            .post("embeddings", &request_body, estimated_tokens)
+            .await?;
+        let mut response_body: EmbeddingResponse =
+            serde_json::from_str(&body).map_err(|e| LlmError::malformed(e, body.clone()))?;
+
+        if response_body.data.len() != texts.len() {
+            return Err(LlmError::malformed(
+                format!(
+                    "Expected {} embeddings, got {}",
+                    texts.len(),
+                    response_body.data.len()
+                ),
+                body,
+            ));
+        }
+        // Servers may return the vectors out of order
+        response_body.data.sort_by_key(|d| d.index);
+        Ok(Embeddings {
+            vectors: response_body
+                .data
+                .into_iter()
+                .map(|d| d.embedding)
+                .collect(),
+            model: self.config.embedding_model.clone(),
+            usage: TokenUsage {
+                prompt_tokens: response_body
+                    .usage
+                    .map_or(estimated_tokens as u32, |u| u.prompt_tokens),
+                completion_tokens: 0,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::ResilienceConfig;
+    use crate::core::resilience::CircuitBreaker;
+    use axum::{routing::post, Json, Router};
+    use std::sync::Arc;
+
+    // Minimal stand-in for a local OpenAI-compatible server
+    async fn start_server() -> String {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(|Json(body): Json<Value>| async move {
+                    let format = body["response_format"]["type"].clone();
+                    Json(json!({
+                        "choices": [{ "message": { "role": "assistant", "content": format!("{{\"format\": {}}}", format) } }],
+                        "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+                    }))
+                }),
+            )
+            .route(
+                "/v1/embeddings",
+                post(|Json(body): Json<Value>| async move {
+                    // Reply in reverse order, each vector filled with its index
+                    let dims = body["dimensions"].as_u64().unwrap_or(3) as usize;
+                    let count = body["input"].as_array().map_or(0, |a| a.len());
+                    let data: Vec<Value> = (0..count)
+                        .rev()
+                        .map(|i| json!({ "index": i, "embedding": vec![i as f32; dims] }))
+                        .collect();
+                    Json(json!({ "data": data }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/v1/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_chat_and_embeddings_round_trip() {
+        let resilience = ResilienceConfig::default();
+        let http = ResilientClient::new(&resilience, Arc::new(CircuitBreaker::new(&resilience)));
+        let client = OpenAiClient::new(
+            &OpenAiConfig {
+                base_url: start_server().await,
+                ..OpenAiConfig::default()
+            },
+            http,
+        );
+
+        let json = client
+            .generate_json(
+                "Extract questions",
+                Some(&json!({ "type": "object" })),
+                &GenerationParams::default(),
+            )
+            .await
+            .expect("chat completion failed");
+        assert_eq!(json.text, r#"{"format": "json_schema"}"#);
+        assert_eq!(
+            json.usage,
+            TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 5
+            }
+        );
+
+        let texts = vec!["First".to_string(), "Second".to_string()];
+        let embeddings = client
+            .embed_batch(&texts, EmbeddingTask::RetrievalDocument, Some(768))
+            .await
+            .expect("embedding failed");
+        assert_eq!(embeddings.vectors.len(), 2);
+        assert_eq!(embeddings.vectors[0], vec![0.0; 768]);
+        assert_eq!(embeddings.vectors[1], vec![1.0; 768]);
+    }
+}
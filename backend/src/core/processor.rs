@@ -1,109 +1,769 @@
-use crate::core::gemini_client::GeminiClient;
+use crate::core::chunker;
+use crate::core::config::{Config, GenerationParams};
+use crate::core::llm_error::LlmError;
+use crate::core::materials::{self, MaterialStatus};
+use crate::core::prompts::{self, PromptTemplate};
+use crate::core::providers::Providers;
+use crate::core::questions::{PassageDraft, Question, QuestionDraft};
+use crate::core::readability;
+use crate::core::structured::generate_structured;
+use crate::core::traits::{EmbeddingTask, LlmProvider};
+use crate::core::usage::{Feature, UsageRecorder, UsageScope};
+use crate::core::verification::{Verification, VerificationStatus, Verifier};
+use futures_util::{stream, StreamExt};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use sqlx::PgPool;
+use std::collections::HashSet;
+use std::fmt;
 use uuid::Uuid;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug)]
 struct ExtractedQuestion {
+    #[schemars(schema_with = "topic_schema")]
     topic: String,
+    #[schemars(schema_with = "difficulty_schema")]
     difficulty: String,
-    content: serde_json::Value, // Flexible JSON content
+    content: QuestionDraft,
     text_for_embedding: String, // Text used to generate the vector
 }
 
-#[derive(Deserialize, Debug)]
+// Passages come first, so the model writes a passage before the questions
+// that refer to it by index
+#[derive(Deserialize, JsonSchema, Debug)]
 struct ExtractionResponse {
+    passages: Vec<PassageDraft>,
     questions: Vec<ExtractedQuestion>,
 }
 
+// Why processing a material failed; the worker uses it to decide between
+// retrying the job and dead-lettering it
+#[derive(Debug)]
+pub enum ProcessError {
+    Llm(LlmError),
+    Database(sqlx::Error),
+    // Some questions could not be embedded; a retry embeds only those
+    Embeddings {
+        failed: usize,
+        total: usize,
+        last: LlmError,
+    },
+}
+
+impl ProcessError {
+    pub fn llm_error(&self) -> Option<&LlmError> {
+        match self {
+            ProcessError::Llm(e) | ProcessError::Embeddings { last: e, .. } => Some(e),
+            ProcessError::Database(_) => None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProcessError::Llm(e) => e.is_retryable(),
+            ProcessError::Database(_) => true,
+            ProcessError::Embeddings { last, .. } => last.is_retryable(),
+        }
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::Llm(e) => write!(f, "{}", e),
+            ProcessError::Database(e) => write!(f, "Database error: {}", e),
+            ProcessError::Embeddings {
+                failed,
+                total,
+                last,
+            } => write!(f, "{} of {} embeddings failed: {}", failed, total, last),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+impl From<LlmError> for ProcessError {
+    fn from(e: LlmError) -> Self {
+        ProcessError::Llm(e)
+    }
+}
+
+impl From<sqlx::Error> for ProcessError {
+    fn from(e: sqlx::Error) -> Self {
+        ProcessError::Database(e)
+    }
+}
+
 pub async fn process_material(
     material_id: Uuid,
+    job_id: Option<Uuid>,
     content: String,
-    gemini: GeminiClient,
+    providers: Providers,
     pool: PgPool,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    config: &Config,
+) -> Result<(), ProcessError> {
     println!("Processing material {}", material_id);
+    materials::set_status(&pool, material_id, MaterialStatus::Extracting, None).await?;
 
-    // 1. Extract Questions using Gemini
-    let prompt = format!(
-        "Analyze the following text and extract practice questions for CU-TEP. \
-        Return a JSON object with a key 'questions', which is a list of objects. \
-        Each object must have: 'topic' (reading, listening, error_id), 'difficulty' (easy, medium, hard), \
-        'content' (the actual question structure), and 'text_for_embedding' (a summary or the question text itself). \
-        \n\n TEXT: {}", 
-        content
-    );
-
-    let json_response = match gemini.generate_json(&prompt).await {
-        Ok(json) => json,
-        Err(e) => {
-            eprintln!("Gemini generation failed: {}", e);
-            return Err(e);
-        }
+    // Every model call below is billed to this material and job
+    let recorder = UsageRecorder::new(pool.clone(), &config.usage);
+    let scope = UsageScope {
+        raw_material_id: Some(material_id),
+        job_id,
+        exam_id: None,
     };
+    let llm = recorder.meter_llm(providers.llm.clone(), scope, Feature::Extraction);
+    let embedder = recorder.meter_embedder(providers.embedder.clone(), scope);
 
-    // Clean markdown code blocks if present (Gemini sometimes adds ```json ... ```)
-    let clean_json = json_response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```");
+    // 1. Extract Questions using the LLM, one prompt per chunk of the material.
+    // Skipped on a retry once a previous attempt has saved its questions.
+    let already_extracted = sqlx::query_scalar!(
+        r#"SELECT questions_extracted_at IS NOT NULL AS "extracted!" FROM raw_materials WHERE id = $1"#,
+        material_id
+    )
+    .fetch_one(&pool)
+    .await?;
 
-    let extracted: ExtractionResponse = serde_json::from_str(clean_json)?;
+    if already_extracted {
+        println!(
+            "Questions for material {} already extracted, resuming embeddings",
+            material_id
+        );
+    } else {
+        // Chosen per material, so a retry keeps the same version
+        let params = &config.generation.extraction;
+        let template = config.prompts.select(
+            prompts::EXTRACTION,
+            &params.prompt_versions,
+            material_id.as_u128(),
+        );
+        let chunks = chunker::chunk_text(&content, &config.chunking);
+        let mut per_chunk = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            println!(
+                "Extracting questions from chunk {}/{} of material {}",
+                i + 1,
+                chunks.len(),
+                material_id
+            );
+            per_chunk.push(
+                extract_questions(llm.as_ref(), params, template, chunk, i, chunks.len()).await?,
+            );
+        }
+        let ExtractionResponse {
+            passages,
+            questions,
+        } = merge_chunks(per_chunk);
 
-    // 2. Save Questions and Generate Embeddings
-    for q in extracted.questions {
-        // Insert Question
-        let q_id = Uuid::new_v4();
-        sqlx::query!(
-            "INSERT INTO questions (id, raw_material_id, topic, content, difficulty_level) VALUES ($1, $2, $3, $4, $5)",
-            q_id, material_id, q.topic, q.content, q.difficulty
+        // 2. Save all Questions and their passages atomically. Items that do
+        // not fit their CU-TEP type are kept aside for review instead.
+        let (valid, quarantined) = check_questions(questions, &passages);
+        if !quarantined.is_empty() {
+            println!(
+                "Quarantined {} of {} questions from material {}",
+                quarantined.len(),
+                valid.len() + quarantined.len(),
+                material_id
+            );
+        }
+        save_questions(
+            &pool,
+            material_id,
+            &passages,
+            &valid,
+            &quarantined,
+            &template.id(),
         )
-        .execute(&pool)
         .await?;
+    }
 
-        // Generate Embedding
-        let embedding_values = match gemini.generate_embedding(&q.text_for_embedding).await {
-            Ok(v) => v,
+    // 3. Verify the answer key of every question not checked yet
+    if config.verification.samples > 0 {
+        materials::set_status(&pool, material_id, MaterialStatus::Verifying, None).await?;
+        let verifier = Verifier::new(
+            recorder.meter_llm(providers.llm.clone(), scope, Feature::Verification),
+            config.generation.verification.clone(),
+            &config.prompts,
+            config.verification.samples,
+        );
+        verify_questions(
+            &pool,
+            material_id,
+            &verifier,
+            config.verification.concurrency,
+        )
+        .await?;
+    }
+
+    // 4. Generate Embeddings for every question and passage that does not
+    // have one yet
+    materials::set_status(&pool, material_id, MaterialStatus::Embedding, None).await?;
+    let pending = pending_embeddings(&pool, material_id).await?;
+
+    // Batches are embedded concurrently and saved as each one completes, so
+    // a crash part-way keeps what was already stored
+    let batches: Vec<(Vec<EmbeddingTarget>, Vec<String>)> = pending
+        .chunks(config.embedding.batch_size)
+        .map(|batch| batch.iter().cloned().unzip())
+        .collect();
+    let mut results = stream::iter(batches)
+        .map(|(targets, texts)| {
+            let embedder = embedder.clone();
+            let dimensions = config.embedding.dimensions;
+            async move {
+                let result = embedder
+                    .embed_batch(&texts, EmbeddingTask::RetrievalDocument, dimensions)
+                    .await;
+                (targets, texts, result)
+            }
+        })
+        .buffer_unordered(config.embedding.concurrency);
+
+    let mut failed = 0;
+    let mut last_error = None;
+    while let Some((targets, texts, result)) = results.next().await {
+        let vectors = match result {
+            Ok(embeddings) => embeddings.vectors,
             Err(e) => {
-                eprintln!("Failed to generate embedding for question {}: {}", q_id, e);
-                // Continue to next question or returning error?
-                // For now, let's log and continue, or fill with zeros?
-                // Better to fail this question's embedding but keep the question?
-                // Let's return error to fail the batch for now for safety.
-                return Err(e);
+                // Keep going so one bad batch does not hold back the rest;
+                // the retry picks up only what is still pending
+                eprintln!(
+                    "Failed to generate embeddings for {} items: {}",
+                    targets.len(),
+                    e
+                );
+                save_embedding_error(&pool, &targets, &e.to_string()).await?;
+                failed += targets.len();
+                last_error = Some(e);
+                continue;
             }
         };
 
-        let embedding = pgvector::Vector::from(embedding_values.clone());
+        for ((target, text), values) in targets.into_iter().zip(&texts).zip(vectors) {
+            match target {
+                EmbeddingTarget::Question(id) => save_embedding(&pool, id, text, values).await?,
+                EmbeddingTarget::Passage(id) => save_passage_embedding(&pool, id, values).await?,
+            }
+        }
+    }
+
+    if let Some(last) = last_error {
+        return Err(ProcessError::Embeddings {
+            failed,
+            total: pending.len(),
+            last,
+        });
+    }
+
+    // 5. Mark processed
+    materials::set_status(&pool, material_id, MaterialStatus::Done, None).await?;
+
+    println!("Finished processing material {}", material_id);
+    Ok(())
+}
+
+// An extracted item that passed validation
+struct CheckedQuestion {
+    extracted: ExtractedQuestion,
+    question: Question,
+}
+
+// An extracted item that does not fit its type, and why
+struct QuarantinedQuestion {
+    extracted: ExtractedQuestion,
+    errors: Vec<String>,
+}
+
+fn check_questions(
+    questions: Vec<ExtractedQuestion>,
+    passages: &[PassageDraft],
+) -> (Vec<CheckedQuestion>, Vec<QuarantinedQuestion>) {
+    let mut valid = Vec::new();
+    let mut quarantined = Vec::new();
+    for extracted in questions {
+        match extracted.content.clone().validate(passages) {
+            Ok(question) => valid.push(CheckedQuestion {
+                extracted,
+                question,
+            }),
+            Err(errors) => quarantined.push(QuarantinedQuestion { extracted, errors }),
+        }
+    }
+    (valid, quarantined)
+}
+
+// Insert the extracted questions, the passages they refer to, and mark the
+// material as extracted in one transaction, so a failed attempt never leaves
+// a partial set behind. A passage no item refers to is not kept.
+async fn save_questions(
+    pool: &PgPool,
+    material_id: Uuid,
+    passages: &[PassageDraft],
+    questions: &[CheckedQuestion],
+    quarantined: &[QuarantinedQuestion],
+    prompt_version: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
 
-        // Insert Embedding
+    let referenced: HashSet<usize> = questions
+        .iter()
+        .map(|q| &q.extracted)
+        .chain(quarantined.iter().map(|q| &q.extracted))
+        .filter_map(|q| q.content.passage)
+        .collect();
+    let mut passage_ids = vec![None; passages.len()];
+    for (i, passage) in passages.iter().enumerate() {
+        if !referenced.contains(&i) {
+            continue;
+        }
+        let measured = readability::measure(&passage.text);
+        let id = sqlx::query_scalar!(
+            "INSERT INTO passages (raw_material_id, kind, label, content, word_count, sentence_count, flesch_reading_ease) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+            material_id,
+            passage.kind.as_str(),
+            passage.label,
+            passage.text.trim(),
+            measured.word_count,
+            measured.sentence_count,
+            measured.flesch_reading_ease
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        passage_ids[i] = Some(id);
+    }
+    let passage_id = |q: &ExtractedQuestion| {
+        q.content
+            .passage
+            .and_then(|i| passage_ids.get(i).copied().flatten())
+    };
+
+    // Questions of a set keep the order they were extracted in
+    let mut positions = vec![0; passages.len()];
+    for q in questions {
+        let content = serde_json::to_value(&q.question).expect("question serializes");
+        let position = q.extracted.content.passage.map(|i| {
+            positions[i] += 1;
+            positions[i] - 1
+        });
         sqlx::query!(
-            "INSERT INTO embeddings (question_id, chunk_text, embedding) VALUES ($1, $2, $3)",
-            q_id,
-            q.text_for_embedding,
-            embedding as pgvector::Vector
+            "INSERT INTO questions (raw_material_id, topic, question_type, content, difficulty_level, text_for_embedding, prompt_version, passage_id, passage_position) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            material_id,
+            q.extracted.topic,
+            q.question.question_type().as_str(),
+            content,
+            q.extracted.difficulty,
+            q.extracted.text_for_embedding,
+            prompt_version,
+            passage_id(&q.extracted),
+            position
         )
-        .execute(&pool)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for q in quarantined {
+        let content = serde_json::to_value(&q.extracted).expect("question serializes");
+        sqlx::query!(
+            "INSERT INTO quarantined_questions (raw_material_id, content, errors, prompt_version, passage_id) VALUES ($1, $2, $3, $4, $5)",
+            material_id,
+            content,
+            &q.errors,
+            prompt_version,
+            passage_id(&q.extracted)
+        )
+        .execute(&mut *tx)
         .await?;
     }
 
-    // 3. Mark processed
     sqlx::query!(
-        "UPDATE raw_materials SET processed = TRUE WHERE id = $1",
+        "UPDATE raw_materials SET questions_extracted_at = NOW(), updated_at = NOW() WHERE id = $1",
         material_id
     )
-    .execute(&pool)
+    .execute(&mut *tx)
     .await?;
 
-    println!("Finished processing material {}", material_id);
+    tx.commit().await
+}
+
+// Re-answer the typed questions of the material that have not been verified
+// yet and store the outcome. A question that could not be checked keeps the
+// error in `verification_error` and stays pending for the next run of the
+// material; it does not fail the job, whose questions are already stored.
+async fn verify_questions(
+    pool: &PgPool,
+    material_id: Uuid,
+    verifier: &Verifier,
+    concurrency: usize,
+) -> Result<(), sqlx::Error> {
+    let pending = sqlx::query!(
+        r#"
+        SELECT q.id, q.content, p.content AS "passage?"
+        FROM questions q
+        LEFT JOIN passages p ON p.id = q.passage_id
+        WHERE q.raw_material_id = $1 AND q.question_type IS NOT NULL AND q.verification_status IS NULL
+        ORDER BY q.created_at, q.id
+        "#,
+        material_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let questions: Vec<(Uuid, Question, Option<String>)> = pending
+        .into_iter()
+        .filter_map(
+            |row| match serde_json::from_value::<Question>(row.content) {
+                Ok(question) => Some((row.id, question, row.passage)),
+                Err(e) => {
+                    eprintln!("Question {} is not a typed item: {}", row.id, e);
+                    None
+                }
+            },
+        )
+        .collect();
+    let total = questions.len();
+    let mut results = stream::iter(questions)
+        .map(|(id, question, passage)| async move {
+            (
+                id,
+                verifier
+                    .verify(&question, passage.as_deref(), id.as_u128())
+                    .await,
+            )
+        })
+        .buffer_unordered(concurrency);
+
+    let mut failed = 0;
+    while let Some((id, result)) = results.next().await {
+        match result {
+            Ok(verification) => save_verification(pool, id, &verification).await?,
+            Err(e) => {
+                eprintln!("Failed to verify the answer of question {}: {}", id, e);
+                sqlx::query!(
+                    "UPDATE questions SET verification_error = $2 WHERE id = $1",
+                    id,
+                    e.to_string()
+                )
+                .execute(pool)
+                .await?;
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        println!(
+            "{} of {} answer keys of material {} could not be verified",
+            failed, total, material_id
+        );
+    }
+    Ok(())
+}
+
+async fn save_verification(
+    pool: &PgPool,
+    question_id: Uuid,
+    verification: &Verification,
+) -> Result<(), sqlx::Error> {
+    if verification.status != VerificationStatus::Verified {
+        println!(
+            "Question {}: answer key {} (confidence {})",
+            question_id,
+            verification.status.as_str(),
+            verification.confidence
+        );
+    }
+    sqlx::query!(
+        "UPDATE questions SET verification_status = $2, answer_confidence = $3, verification = $4, verified_at = NOW(), verification_error = NULL WHERE id = $1",
+        question_id,
+        verification.status.as_str(),
+        verification.confidence,
+        serde_json::to_value(verification).expect("verification serializes")
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
+// What an embedding is for
+#[derive(Clone, Copy, Debug)]
+enum EmbeddingTarget {
+    Question(Uuid),
+    Passage(Uuid),
+}
+
+// Questions, then passages, of the material that still need an embedding,
+// with the text to embed
+async fn pending_embeddings(
+    pool: &PgPool,
+    material_id: Uuid,
+) -> Result<Vec<(EmbeddingTarget, String)>, sqlx::Error> {
+    let questions = sqlx::query!(
+        r#"
+        SELECT id, COALESCE(text_for_embedding, content::text) AS "text!"
+        FROM questions
+        WHERE raw_material_id = $1 AND embedding_status = 'pending'
+        ORDER BY created_at, id
+        "#,
+        material_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let passages = sqlx::query!(
+        r#"
+        SELECT id, content
+        FROM passages
+        WHERE raw_material_id = $1 AND embedding_status = 'pending'
+        ORDER BY created_at, id
+        "#,
+        material_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let questions = questions
+        .into_iter()
+        .map(|q| (EmbeddingTarget::Question(q.id), q.text));
+    let passages = passages
+        .into_iter()
+        .map(|p| (EmbeddingTarget::Passage(p.id), p.content));
+    Ok(questions.chain(passages).collect())
+}
+
+async fn save_embedding_error(
+    pool: &PgPool,
+    targets: &[EmbeddingTarget],
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let (mut questions, mut passages) = (Vec::new(), Vec::new());
+    for target in targets {
+        match *target {
+            EmbeddingTarget::Question(id) => questions.push(id),
+            EmbeddingTarget::Passage(id) => passages.push(id),
+        }
+    }
+    sqlx::query!(
+        "UPDATE questions SET embedding_error = $2 WHERE id = ANY($1)",
+        &questions,
+        error
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "UPDATE passages SET embedding_error = $2 WHERE id = ANY($1)",
+        &passages,
+        error
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Store the embedding and flip the question to 'done' together
+async fn save_embedding(
+    pool: &PgPool,
+    question_id: Uuid,
+    text: &str,
+    values: Vec<f32>,
+) -> Result<(), sqlx::Error> {
+    let embedding = pgvector::Vector::from(values);
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO embeddings (question_id, chunk_text, embedding) VALUES ($1, $2, $3) ON CONFLICT (question_id) DO NOTHING",
+        question_id,
+        text,
+        embedding as pgvector::Vector
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE questions SET embedding_status = 'done', embedding_error = NULL WHERE id = $1",
+        question_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+// A passage keeps its own embedding
+async fn save_passage_embedding(
+    pool: &PgPool,
+    passage_id: Uuid,
+    values: Vec<f32>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE passages SET embedding = $2, embedding_status = 'done', embedding_error = NULL WHERE id = $1",
+        passage_id,
+        pgvector::Vector::from(values) as pgvector::Vector
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn extract_questions(
+    llm: &dyn LlmProvider,
+    params: &GenerationParams,
+    template: &PromptTemplate,
+    chunk: &str,
+    index: usize,
+    total: usize,
+) -> Result<ExtractionResponse, LlmError> {
+    // Chunks overlap, so a question cut off at either edge is complete in the
+    // neighbouring chunk and can be skipped here
+    let part_note = if total > 1 {
+        format!(
+            "The text is part {} of {} of a longer document. \
+            Skip any passage or question that is cut off at the start or end of the text. ",
+            index + 1,
+            total
+        )
+    } else {
+        String::new()
+    };
+
+    let prompt = template.render(&[("part_note", &part_note), ("text", chunk)]);
+
+    match generate_structured(llm, &prompt, params).await {
+        Ok(extracted) => Ok(extracted),
+        Err(e) => {
+            eprintln!("LLM generation failed: {}", e);
+            Err(e)
+        }
+    }
+}
+
+// Schemas for the fields of `ExtractedQuestion` that stay loosely typed in
+// Rust but are constrained for the model
+fn topic_schema(_: &mut SchemaGenerator) -> Schema {
+    schema_from_json(json!({ "type": "string", "enum": ["reading", "listening", "error_id"] }))
+}
+
+fn difficulty_schema(_: &mut SchemaGenerator) -> Schema {
+    schema_from_json(json!({ "type": "string", "enum": ["easy", "medium", "hard"] }))
+}
+
+fn schema_from_json(value: Value) -> Schema {
+    serde_json::from_value(value).expect("valid JSON Schema")
+}
+
+// Passages and questions that straddle a chunk boundary are extracted from
+// both chunks. Drop exact repeats anywhere in the material, and
+// near-identical ones coming from adjacent chunks (the model rarely words
+// them identically twice). A question only repeats one of the same type and
+// passage with the same choices. Question passage indices are rewritten to
+// point into the merged passages.
+fn merge_chunks(per_chunk: Vec<ExtractionResponse>) -> ExtractionResponse {
+    let mut passages: Vec<(usize, Vec<String>, PassageDraft)> = Vec::new();
+    let mut questions: Vec<(usize, Vec<String>, Vec<String>, ExtractedQuestion)> = Vec::new();
+    // Per chunk, the merged index of each of its passages
+    let mut passage_maps = Vec::with_capacity(per_chunk.len());
+    let mut chunk_questions = Vec::with_capacity(per_chunk.len());
+
+    for (chunk_index, chunk) in per_chunk.into_iter().enumerate() {
+        let mut map = Vec::with_capacity(chunk.passages.len());
+        for passage in chunk.passages {
+            let words = words(&passage.text);
+            let kept = passages.iter().position(|(kept_chunk, kept_words, kept)| {
+                kept.kind == passage.kind && is_repeat(*kept_chunk, kept_words, chunk_index, &words)
+            });
+            map.push(kept.unwrap_or_else(|| {
+                passages.push((chunk_index, words, passage));
+                passages.len() - 1
+            }));
+        }
+        passage_maps.push(map);
+        chunk_questions.push(chunk.questions);
+    }
+
+    // A reference to a passage the chunk does not have points past the end
+    // of the merged passages, so validation reports it
+    let missing = passages.len();
+    for (chunk_index, (map, chunk)) in passage_maps.iter().zip(chunk_questions).enumerate() {
+        for mut q in chunk {
+            q.content.passage = q
+                .content
+                .passage
+                .map(|i| map.get(i).copied().unwrap_or(missing));
+            // Standalone items often share a stem ("Choose the best answer"),
+            // so only one with the same type and choices is a repeat
+            let options: Vec<String> = q
+                .content
+                .options
+                .iter()
+                .map(|o| words(o).join(" "))
+                .collect();
+            let words = question_words(&q);
+            let duplicate = questions
+                .iter()
+                .any(|(kept_chunk, kept_words, kept_options, kept)| {
+                    kept.content.question_type == q.content.question_type
+                        && kept.content.passage == q.content.passage
+                        && *kept_options == options
+                        && is_repeat(*kept_chunk, kept_words, chunk_index, &words)
+                });
+            if !duplicate {
+                questions.push((chunk_index, words, options, q));
+            }
+        }
+    }
+
+    ExtractionResponse {
+        passages: passages.into_iter().map(|(_, _, p)| p).collect(),
+        questions: questions.into_iter().map(|(_, _, _, q)| q).collect(),
+    }
+}
+
+fn is_repeat(kept_chunk: usize, kept_words: &[String], chunk: usize, words: &[String]) -> bool {
+    kept_words == words
+        || (chunk == kept_chunk + 1 && similarity(kept_words, words) >= NEAR_DUPLICATE_SIMILARITY)
+}
+
+// Word overlap at which two passages or questions from adjacent chunks count
+// as the same
+const NEAR_DUPLICATE_SIMILARITY: f64 = 0.85;
+
+// Lowercased words of the question stem, falling back to the embedding text
+// when the stem is empty
+fn question_words(q: &ExtractedQuestion) -> Vec<String> {
+    match q.content.stem.trim() {
+        "" => words(&q.text_for_embedding),
+        stem => words(stem),
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+// Jaccard similarity of the two word sets
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    let a: HashSet<&String> = a.iter().collect();
+    let b: HashSet<&String> = b.iter().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::config::Config;
+    use crate::core::config::{
+        CacheConfig, ChunkConfig, EmbeddingConfig, FixtureConfig, GenerationSettings,
+        ProviderConfig, ResilienceConfig, Secret, UsageConfig, VerificationConfig, WorkerConfig,
+        DEFAULT_MAX_BODY_BYTES,
+    };
+    use crate::core::prompts::PromptRegistry;
+    use crate::core::questions::{PassageKind, QuestionType};
     use sqlx::Row;
 
     #[tokio::test]
@@ -114,15 +774,26 @@ mod tests {
 
         let config = Config {
             database_url: database_url.clone(),
-            gemini_api_key: "dummy_key".to_string(),
+            gemini_api_keys: vec![Secret::new("dummy_key")],
             mock_gemini: true,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            worker: WorkerConfig::default(),
+            chunking: ChunkConfig::default(),
+            embedding: EmbeddingConfig::default(),
+            generation: GenerationSettings::default(),
+            providers: ProviderConfig::default(),
+            resilience: ResilienceConfig::default(),
+            usage: UsageConfig::default(),
+            cache: CacheConfig::default(),
+            fixtures: FixtureConfig::default(),
+            verification: VerificationConfig::default(),
+            prompts: PromptRegistry::builtin(),
         };
-        let gemini = GeminiClient::new(&config);
-
         // 2. Setup DB Pool
         let pool = PgPool::connect(&config.database_url)
             .await
             .expect("Failed to connect to DB");
+        let providers = Providers::from_config(&config, pool.clone());
 
         // 3. Insert Test Data (Raw Material)
         let raw_id = Uuid::new_v4();
@@ -140,9 +811,11 @@ mod tests {
         // 4. Run Processor
         let result = process_material(
             raw_id,
+            None,
             "Unit Test Content".to_string(),
-            gemini,
+            providers,
             pool.clone(),
+            &config,
         )
         .await;
 
@@ -159,6 +832,52 @@ mod tests {
                 .expect("Failed to fetch question count")
                 .get(0);
         assert!(questions_count > 0, "No questions were generated");
+        let prompt_version: Option<String> =
+            sqlx::query("SELECT prompt_version FROM questions WHERE raw_material_id = $1 LIMIT 1")
+                .bind(raw_id)
+                .fetch_one(&pool)
+                .await
+                .expect("Failed to fetch prompt version")
+                .get(0);
+        assert_eq!(prompt_version.as_deref(), Some("extraction@v4"));
+        let question_type: Option<String> =
+            sqlx::query("SELECT question_type FROM questions WHERE raw_material_id = $1 LIMIT 1")
+                .bind(raw_id)
+                .fetch_one(&pool)
+                .await
+                .expect("Failed to fetch question type")
+                .get(0);
+        assert_eq!(question_type.as_deref(), Some("reading_comprehension"));
+
+        // The mock model agrees with the answer key
+        let verification = sqlx::query!(
+            "SELECT verification_status, answer_confidence FROM questions WHERE raw_material_id = $1",
+            raw_id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to fetch verification");
+        assert_eq!(
+            verification.verification_status.as_deref(),
+            Some("verified")
+        );
+        assert_eq!(verification.answer_confidence, Some(1.0));
+
+        // The passage is stored once, measured, embedded and linked
+        let passage = sqlx::query!(
+            r#"SELECT p.kind, p.label, p.word_count, p.embedding_status, q.passage_position
+            FROM passages p JOIN questions q ON q.passage_id = p.id
+            WHERE p.raw_material_id = $1"#,
+            raw_id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to fetch passage");
+        assert_eq!(passage.kind, "reading");
+        assert_eq!(passage.label.as_deref(), Some("Passage 1"));
+        assert_eq!(passage.word_count, Some(2));
+        assert_eq!(passage.embedding_status, "done");
+        assert_eq!(passage.passage_position, Some(0));
 
         // Check Embedding (we just check if any embedding exists for the questions linked to this raw material)
         // Since we don't know the question ID easily without querying, we join or verify count.
@@ -173,6 +892,15 @@ mod tests {
 
         assert!(embeddings_count > 0, "No embeddings were generated");
 
+        // Check Lifecycle
+        let status: String = sqlx::query("SELECT status FROM raw_materials WHERE id = $1")
+            .bind(raw_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to fetch material status")
+            .get(0);
+        assert_eq!(status, "done");
+
         // Cleanup (Optional, but good for local dev)
         // Ideally we use a transaction that rolls back, but for this simple test suite we might just leave it or delete.
         sqlx::query!("DELETE FROM embeddings USING questions WHERE embeddings.question_id = questions.id AND questions.raw_material_id = $1", raw_id)
@@ -181,9 +909,353 @@ mod tests {
             .execute(&pool)
             .await
             .ok();
+        sqlx::query!("DELETE FROM passages WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+        sqlx::query!(
+            "DELETE FROM material_events WHERE raw_material_id = $1",
+            raw_id
+        )
+        .execute(&pool)
+        .await
+        .ok();
+        sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+    }
+
+    #[tokio::test]
+    async fn test_questions_that_do_not_fit_are_quarantined() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let raw_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO raw_materials (id, url, content, source_type) VALUES ($1, $2, $3, $4)",
+            raw_id,
+            "http://test.com/quarantine-test",
+            "Quarantine Test Content",
+            "unit-test"
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert raw material");
+
+        let mut unanswerable = question("Which word is wrong?");
+        unanswerable.content.answer = "E".to_string();
+        let passages = [passage("Bees are busy."), passage("Never referred to.")];
+        let (valid, quarantined) = check_questions(
+            vec![question("What is the main idea?"), unanswerable],
+            &passages,
+        );
+        assert_eq!((valid.len(), quarantined.len()), (1, 1));
+        save_questions(
+            &pool,
+            raw_id,
+            &passages,
+            &valid,
+            &quarantined,
+            "extraction@v3",
+        )
+        .await
+        .expect("Failed to save questions");
+
+        // Only the passage in use is kept, shared by both items
+        let passage_ids =
+            sqlx::query_scalar!("SELECT id FROM passages WHERE raw_material_id = $1", raw_id)
+                .fetch_all(&pool)
+                .await
+                .expect("Failed to fetch passages");
+        assert_eq!(passage_ids.len(), 1);
+
+        let saved = sqlx::query!(
+            r#"SELECT question_type, content->>'answer' AS answer, passage_id FROM questions WHERE raw_material_id = $1"#,
+            raw_id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to fetch question");
+        assert_eq!(
+            saved.question_type.as_deref(),
+            Some("reading_comprehension")
+        );
+        assert_eq!(saved.answer.as_deref(), Some("A"));
+        assert_eq!(saved.passage_id, Some(passage_ids[0]));
+        let kept = sqlx::query!(
+            r#"SELECT content->'content'->>'stem' AS stem, errors, passage_id FROM quarantined_questions WHERE raw_material_id = $1"#,
+            raw_id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to fetch quarantined question");
+        assert_eq!(kept.stem.as_deref(), Some("Which word is wrong?"));
+        assert_eq!(
+            kept.errors,
+            ["answer: must be the letter of one of the 2 options (got E)"]
+        );
+        assert_eq!(kept.passage_id, Some(passage_ids[0]));
+
+        sqlx::query!("DELETE FROM questions WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+        sqlx::query!("DELETE FROM passages WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+        sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+    }
+
+    #[tokio::test]
+    async fn test_retry_only_embeds_missing_questions() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let config = Config {
+            database_url: database_url.clone(),
+            gemini_api_keys: vec![Secret::new("dummy_key")],
+            mock_gemini: true,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            worker: WorkerConfig::default(),
+            chunking: ChunkConfig::default(),
+            embedding: EmbeddingConfig::default(),
+            generation: GenerationSettings::default(),
+            providers: ProviderConfig::default(),
+            resilience: ResilienceConfig::default(),
+            usage: UsageConfig::default(),
+            cache: CacheConfig::default(),
+            fixtures: FixtureConfig::default(),
+            verification: VerificationConfig::default(),
+            prompts: PromptRegistry::builtin(),
+        };
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+        let providers = Providers::from_config(&config, pool.clone());
+
+        // A material whose previous attempt saved its questions but only
+        // embedded the first one
+        let raw_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO raw_materials (id, url, content, source_type, questions_extracted_at) VALUES ($1, $2, $3, $4, NOW())",
+            raw_id,
+            "http://test.com/resume-test",
+            "Resume Test Content",
+            "unit-test"
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert raw material");
+
+        let embedded_id = Uuid::new_v4();
+        let pending_id = Uuid::new_v4();
+        for (id, status) in [(embedded_id, "done"), (pending_id, "pending")] {
+            sqlx::query!(
+                "INSERT INTO questions (id, raw_material_id, topic, content, text_for_embedding, embedding_status) VALUES ($1, $2, 'reading', '{}', 'Saved question', $3)",
+                id,
+                raw_id,
+                status
+            )
+            .execute(&pool)
+            .await
+            .expect("Failed to insert question");
+        }
+        sqlx::query!(
+            "INSERT INTO embeddings (question_id, chunk_text, embedding) VALUES ($1, 'Saved question', $2)",
+            embedded_id,
+            pgvector::Vector::from(vec![0.2; 768]) as pgvector::Vector
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert embedding");
+
+        let result = process_material(
+            raw_id,
+            None,
+            "Resume Test Content".to_string(),
+            providers,
+            pool.clone(),
+            &config,
+        )
+        .await;
+        assert!(result.is_ok(), "Processor failed: {:?}", result.err());
+
+        // No re-extraction, one new embedding, nothing left pending
+        let counts = sqlx::query(
+            "SELECT (SELECT count(*) FROM questions WHERE raw_material_id = $1), \
+                    (SELECT count(*) FROM questions WHERE raw_material_id = $1 AND embedding_status = 'pending'), \
+                    (SELECT count(*) FROM embeddings e JOIN questions q ON e.question_id = q.id WHERE q.raw_material_id = $1)",
+        )
+        .bind(raw_id)
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to fetch counts");
+        assert_eq!(counts.get::<i64, _>(0), 2);
+        assert_eq!(counts.get::<i64, _>(1), 0);
+        assert_eq!(counts.get::<i64, _>(2), 2);
+
+        sqlx::query!("DELETE FROM embeddings USING questions WHERE embeddings.question_id = questions.id AND questions.raw_material_id = $1", raw_id)
+            .execute(&pool).await.ok();
+        sqlx::query!("DELETE FROM questions WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .ok();
+        sqlx::query!(
+            "DELETE FROM material_events WHERE raw_material_id = $1",
+            raw_id
+        )
+        .execute(&pool)
+        .await
+        .ok();
         sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
             .execute(&pool)
             .await
             .ok();
     }
+
+    fn question(text: &str) -> ExtractedQuestion {
+        ExtractedQuestion {
+            topic: "reading".to_string(),
+            difficulty: "medium".to_string(),
+            content: QuestionDraft {
+                question_type: QuestionType::ReadingComprehension,
+                stem: text.to_string(),
+                options: vec!["Yes".to_string(), "No".to_string()],
+                answer: "A".to_string(),
+                explanation: None,
+                passage: Some(0),
+                correction: None,
+            },
+            text_for_embedding: text.to_string(),
+        }
+    }
+
+    fn passage(text: &str) -> PassageDraft {
+        PassageDraft {
+            kind: PassageKind::Reading,
+            label: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn on_passage(passage: usize, text: &str) -> ExtractedQuestion {
+        let mut q = question(text);
+        q.content.passage = Some(passage);
+        q
+    }
+
+    #[test]
+    fn test_merge_drops_questions_repeated_across_chunks() {
+        let bees = "Bees are busy insects. They make honey in the summer.";
+        let merged = merge_chunks(vec![
+            ExtractionResponse {
+                passages: vec![passage(bees)],
+                questions: vec![
+                    question("What is the main idea of the passage?"),
+                    question("Why did the author mention bees in paragraph 2?"),
+                ],
+            },
+            ExtractionResponse {
+                // The same passage, seen again by the overlapping chunk
+                passages: vec![passage(
+                    "Bees are busy insects. They make the honey in the summer.",
+                )],
+                questions: vec![
+                    // Same boundary question, worded slightly differently
+                    question("Why did the author mention the bees in paragraph 2?"),
+                    question("What does the word 'busy' in line 4 mean?"),
+                ],
+            },
+            ExtractionResponse {
+                passages: vec![passage("Ants are busy too."), passage(bees)],
+                questions: vec![
+                    // Only exact repeats are dropped between distant chunks
+                    on_passage(1, "what is the MAIN idea of the passage"),
+                    on_passage(1, "why did the author mention bees in paragraph 2"),
+                    // A different passage may ask the same question
+                    on_passage(0, "What is the main idea of the passage?"),
+                    on_passage(2, "Which passage is this?"),
+                ],
+            },
+        ]);
+
+        let passages: Vec<&str> = merged.passages.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(passages, [bees, "Ants are busy too."]);
+        let texts: Vec<(&str, Option<usize>)> = merged
+            .questions
+            .iter()
+            .map(|q| (q.text_for_embedding.as_str(), q.content.passage))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("What is the main idea of the passage?", Some(0)),
+                ("Why did the author mention bees in paragraph 2?", Some(0)),
+                ("What does the word 'busy' in line 4 mean?", Some(0)),
+                ("What is the main idea of the passage?", Some(1)),
+                // Its chunk has no third passage
+                ("Which passage is this?", Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_standalone_items_sharing_a_stem() {
+        let stem = "Choose the best answer to complete the sentence.";
+        let standalone = |options: [&str; 2]| {
+            let mut q = question(stem);
+            q.content.question_type = QuestionType::SentenceCompletion;
+            q.content.passage = None;
+            q.content.options = options.iter().map(|o| o.to_string()).collect();
+            q
+        };
+        let unlabelled = |options: [&str; 2]| {
+            let mut q = standalone(options);
+            q.content.stem = String::new();
+            q.text_for_embedding = "Sentence completion item".to_string();
+            q
+        };
+        let merged = merge_chunks(vec![
+            ExtractionResponse {
+                passages: vec![],
+                questions: vec![
+                    standalone(["goes", "went"]),
+                    standalone(["rarely", "seldom ever"]),
+                    unlabelled(["at", "on"]),
+                    unlabelled(["in", "by"]),
+                ],
+            },
+            ExtractionResponse {
+                passages: vec![],
+                questions: vec![
+                    // Seen again by the overlapping chunk
+                    standalone(["goes", "went"]),
+                    standalone(["has been", "had been"]),
+                ],
+            },
+        ]);
+
+        let options: Vec<String> = merged
+            .questions
+            .iter()
+            .map(|q| q.content.options.join("/"))
+            .collect();
+        assert_eq!(
+            options,
+            [
+                "goes/went",
+                "rarely/seldom ever",
+                "at/on",
+                "in/by",
+                "has been/had been"
+            ]
+        );
+    }
 }
@@ -0,0 +1,365 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// Prompts the code renders and the variables it supplies to each. A template
+// may use any subset of them.
+pub const EXTRACTION: &str = "extraction";
+pub const EXAM: &str = "exam";
+pub const VERIFICATION: &str = "verification";
+const KNOWN_PROMPTS: &[(&str, &[&str])] = &[
+    (EXTRACTION, &["part_note", "text"]),
+    (EXAM, &["topic", "difficulty"]),
+    (VERIFICATION, &["question"]),
+];
+
+// Placeholder replaced by the rendered few-shot examples
+const EXAMPLES_PLACEHOLDER: &str = "examples";
+
+// Shipped with the binary; PROMPT_DIR can add more versions
+const BUILTIN: &[&str] = &[
+    include_str!("../../prompts/extraction.v1.json"),
+    include_str!("../../prompts/exam.v1.json"),
+    include_str!("../../prompts/extraction.v2.json"),
+    include_str!("../../prompts/extraction.v3.json"),
+    include_str!("../../prompts/exam.v2.json"),
+    include_str!("../../prompts/verification.v1.json"),
+    include_str!("../../prompts/extraction.v4.json"),
+];
+
+// One version of a prompt. A published version is never edited: a changed
+// prompt gets a new version so results stay traceable to the exact text.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: u32,
+    #[serde(default)]
+    pub description: String,
+    // Names used as `{{name}}` in `template`
+    pub variables: Vec<String>,
+    pub template: String,
+    // Rendered in place of `{{examples}}`
+    #[serde(default)]
+    pub examples: Vec<FewShotExample>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FewShotExample {
+    pub input: String,
+    // Strings are shown as-is, anything else as JSON
+    pub output: Value,
+}
+
+impl PromptTemplate {
+    // Recorded with what the prompt produced, e.g. `extraction@v2`
+    pub fn id(&self) -> String {
+        format!("{}@v{}", self.name, self.version)
+    }
+
+    // Fill in the variables. Every variable the template declares must be
+    // supplied; `validate` guarantees callers do. The template is scanned
+    // once, so a value that itself contains `{{...}}` (e.g. scraped text) is
+    // inserted as-is.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        for variable in &self.variables {
+            if !values.iter().any(|(name, _)| name == variable) {
+                panic!("{} needs variable {}", self.id(), variable);
+            }
+        }
+        let examples = (!self.examples.is_empty()).then(|| self.render_examples());
+
+        let mut prompt = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + end].trim();
+            let value = match name {
+                EXAMPLES_PLACEHOLDER => examples.as_deref(),
+                _ => values
+                    .iter()
+                    .find(|(variable, _)| *variable == name)
+                    .map(|(_, value)| *value),
+            };
+            prompt.push_str(&rest[..start]);
+            prompt.push_str(value.unwrap_or(&rest[start..start + end + 2]));
+            rest = &rest[start + end + 2..];
+        }
+        prompt.push_str(rest);
+        prompt
+    }
+
+    fn render_examples(&self) -> String {
+        self.examples
+            .iter()
+            .enumerate()
+            .map(|(i, example)| {
+                let output = match &example.output {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                format!(
+                    "Example {}:\nInput:\n{}\nOutput:\n{}",
+                    i + 1,
+                    example.input,
+                    output
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    // A template must belong to a known prompt, only declare variables the
+    // code supplies, and use exactly the placeholders it declares
+    fn validate(&self) -> Result<(), String> {
+        let supplied = KNOWN_PROMPTS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, variables)| *variables)
+            .ok_or_else(|| format!("{}: unknown prompt {}", self.id(), self.name))?;
+
+        for variable in &self.variables {
+            if !supplied.contains(&variable.as_str()) {
+                return Err(format!(
+                    "{}: unknown variable {} (available: {})",
+                    self.id(),
+                    variable,
+                    supplied.join(", ")
+                ));
+            }
+        }
+        for used in placeholders(&self.template) {
+            let declared = self.variables.iter().any(|v| *v == used);
+            let examples = used == EXAMPLES_PLACEHOLDER && !self.examples.is_empty();
+            if !declared && !examples {
+                return Err(format!("{}: {{{{{}}}}} is not declared", self.id(), used));
+            }
+        }
+        for variable in &self.variables {
+            if !self.template.contains(&placeholder(variable)) {
+                return Err(format!(
+                    "{}: {} is declared but unused",
+                    self.id(),
+                    variable
+                ));
+            }
+        }
+        if !self.examples.is_empty() && !self.template.contains(&placeholder(EXAMPLES_PLACEHOLDER))
+        {
+            return Err(format!(
+                "{}: has examples but no {{{{examples}}}}",
+                self.id()
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn placeholder(name: &str) -> String {
+    format!("{{{{{}}}}}", name)
+}
+
+// Names of the `{{name}}` placeholders in a template
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        names.push(rest[start + 2..start + end].trim());
+        rest = &rest[start + end + 2..];
+    }
+    names
+}
+
+// Every known version of every prompt
+#[derive(Clone)]
+pub struct PromptRegistry {
+    templates: Arc<HashMap<String, BTreeMap<u32, PromptTemplate>>>,
+}
+
+// Lists versions only; the templates themselves are long
+impl fmt::Debug for PromptRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ids: Vec<String> = self
+            .templates
+            .values()
+            .flat_map(|versions| versions.values().map(|t| t.id()))
+            .collect();
+        ids.sort();
+        f.debug_tuple("PromptRegistry").field(&ids).finish()
+    }
+}
+
+impl PromptRegistry {
+    pub fn builtin() -> Self {
+        Self::from_sources(
+            BUILTIN
+                .iter()
+                .map(|s| ("built-in".to_string(), s.to_string())),
+        )
+        .expect("built-in prompts are valid")
+    }
+
+    // Built-in prompts plus every `*.json` template in `dir`. A version that
+    // already exists may be repeated but not changed.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let mut sources: Vec<(String, String)> = BUILTIN
+            .iter()
+            .map(|s| ("built-in".to_string(), s.to_string()))
+            .collect();
+        let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let body =
+                    fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                sources.push((path.display().to_string(), body));
+            }
+        }
+        Self::from_sources(sources.into_iter())
+    }
+
+    fn from_sources(sources: impl Iterator<Item = (String, String)>) -> Result<Self, String> {
+        let mut templates: HashMap<String, BTreeMap<u32, PromptTemplate>> = HashMap::new();
+        for (origin, body) in sources {
+            let template: PromptTemplate =
+                serde_json::from_str(&body).map_err(|e| format!("{}: {}", origin, e))?;
+            template
+                .validate()
+                .map_err(|e| format!("{}: {}", origin, e))?;
+            let versions = templates.entry(template.name.clone()).or_default();
+            if versions
+                .get(&template.version)
+                .is_some_and(|existing| *existing != template)
+            {
+                return Err(format!(
+                    "{}: {} already exists with different content; add a new version instead",
+                    origin,
+                    template.id()
+                ));
+            }
+            versions.insert(template.version, template);
+        }
+        Ok(Self {
+            templates: Arc::new(templates),
+        })
+    }
+
+    pub fn get(&self, name: &str, version: u32) -> Option<&PromptTemplate> {
+        self.templates.get(name)?.get(&version)
+    }
+
+    // The template to use for `name`: the latest version when `versions` is
+    // empty, otherwise one of `versions` chosen by `key`, so the same key
+    // always gets the same version (an even A/B split across keys)
+    pub fn select(&self, name: &str, versions: &[u32], key: u128) -> &PromptTemplate {
+        let all = &self.templates[name];
+        if versions.is_empty() {
+            return all.values().next_back().expect("prompt has a version");
+        }
+        let version = versions[(key % versions.len() as u128) as usize];
+        &all[&version]
+    }
+
+    // Startup check that every configured version exists
+    pub fn check_versions(&self, name: &str, versions: &[u32]) -> Result<(), String> {
+        for version in versions {
+            if self.get(name, *version).is_none() {
+                return Err(format!("prompt {}@v{} does not exist", name, version));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn template(value: Value) -> Result<PromptTemplate, String> {
+        let template: PromptTemplate = serde_json::from_value(value).unwrap();
+        template.validate().map(|_| template)
+    }
+
+    #[test]
+    fn test_templates_render_variables_and_examples() {
+        let v2 = template(json!({
+            "name": "exam",
+            "version": 2,
+            "variables": ["topic", "difficulty"],
+            "template": "{{examples}}\n\nWrite a {{difficulty}} {{topic}} question.",
+            "examples": [
+                { "input": "easy grammar", "output": { "question": "She ___ tea." } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            v2.render(&[("topic", "reading"), ("difficulty", "hard")]),
+            "Example 1:\nInput:\neasy grammar\nOutput:\n{\"question\":\"She ___ tea.\"}\n\nWrite a hard reading question."
+        );
+        // Values are not scanned for placeholders again
+        assert_eq!(
+            v2.render(&[("topic", "{{difficulty}}"), ("difficulty", "{{topic}}")]),
+            "Example 1:\nInput:\neasy grammar\nOutput:\n{\"question\":\"She ___ tea.\"}\n\nWrite a {{topic}} {{difficulty}} question."
+        );
+
+        let undeclared = template(json!({
+            "name": "exam", "version": 3, "variables": ["topic"],
+            "template": "{{topic}} at {{difficulty}}"
+        }));
+        assert!(undeclared
+            .unwrap_err()
+            .contains("{{difficulty}} is not declared"));
+        let unknown = template(json!({
+            "name": "exam", "version": 3, "variables": ["user"], "template": "{{user}}"
+        }));
+        assert!(unknown.unwrap_err().contains("unknown variable user"));
+    }
+
+    #[test]
+    fn test_registry_selects_versions() {
+        let exam_v3 = json!({
+            "name": "exam",
+            "version": 3,
+            "variables": ["topic"],
+            "template": "Write a {{topic}} question."
+        });
+        let sources = BUILTIN
+            .iter()
+            .map(|s| s.to_string())
+            .chain([exam_v3.to_string(), BUILTIN[1].to_string()])
+            .map(|body| ("test".to_string(), body));
+        let registry = PromptRegistry::from_sources(sources).unwrap();
+
+        assert_eq!(registry.select(EXAM, &[], 7).id(), "exam@v3");
+        let split: Vec<u32> = (0..4u128)
+            .map(|key| registry.select(EXAM, &[1, 3], key).version)
+            .collect();
+        assert_eq!(split, [1, 3, 1, 3]);
+        assert!(registry
+            .select(EXTRACTION, &[], 0)
+            .render(&[("part_note", ""), ("text", "She goes.")])
+            .ends_with("TEXT: She goes."));
+        assert!(registry.check_versions(EXAM, &[1, 3]).is_ok());
+        assert!(registry.check_versions(EXAM, &[9]).is_err());
+
+        let changed = json!({
+            "name": "exam",
+            "version": 3,
+            "variables": [],
+            "template": "Write a question."
+        });
+        let sources = [exam_v3.to_string(), changed.to_string()]
+            .into_iter()
+            .map(|body| ("test".to_string(), body));
+        assert!(PromptRegistry::from_sources(sources).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::core::cache::ResponseCache;
+use crate::core::config::{Config, FixtureMode, GenerationParams, ProviderKind};
+use crate::core::fixtures::FixtureStore;
+use crate::core::gemini_client::GeminiClient;
+use crate::core::openai_client::OpenAiClient;
+use crate::core::resilience::{CircuitBreaker, ResilientClient};
+use crate::core::traits::{EmbeddingProvider, LlmProvider};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+// The model backends selected in `Config`, shared by the worker and engines
+#[derive(Clone)]
+pub struct Providers {
+    pub llm: Arc<dyn LlmProvider>,
+    pub embedder: Arc<dyn EmbeddingProvider>,
+    // Opened by repeated provider failures; the worker pauses while it is open
+    pub breaker: Arc<CircuitBreaker>,
+}
+
+impl Providers {
+    pub fn from_config(config: &Config, pool: PgPool) -> Self {
+        let breaker = Arc::new(CircuitBreaker::new(&config.resilience));
+
+        // One client (and so one rate limiter) per provider, shared by
+        // generation and embeddings when both use it
+        let gemini_http = ResilientClient::new(&config.resilience, breaker.clone());
+        let openai_http = ResilientClient::new(&config.resilience, breaker.clone());
+        let gemini = GeminiClient::new(config, gemini_http);
+        let openai = OpenAiClient::new(&config.providers.openai, openai_http);
+
+        let mut llm: Arc<dyn LlmProvider> = match config.providers.llm {
+            ProviderKind::Gemini => Arc::new(gemini.clone()),
+            ProviderKind::OpenAi => Arc::new(openai.clone()),
+        };
+        let mut embedder: Arc<dyn EmbeddingProvider> = match config.providers.embedding {
+            ProviderKind::Gemini => Arc::new(gemini),
+            ProviderKind::OpenAi => Arc::new(openai),
+        };
+        println!(
+            "Using {:?} for generation and {:?} for embeddings",
+            config.providers.llm, config.providers.embedding
+        );
+        let default_model = match config.providers.llm {
+            ProviderKind::Gemini => &config.providers.gemini.chat_model,
+            ProviderKind::OpenAi => &config.providers.openai.chat_model,
+        };
+
+        let embedding_model = match config.providers.embedding {
+            ProviderKind::Gemini => &config.providers.gemini.embedding_model,
+            ProviderKind::OpenAi => &config.providers.openai.embedding_model,
+        };
+
+        // Fixtures sit closest to the provider so replay stands in for it
+        if let Some(fixtures) = FixtureStore::new(&config.fixtures) {
+            llm = fixtures.wrap_llm(
+                llm,
+                provider_id(config, config.providers.llm),
+                default_model.clone(),
+            );
+            embedder = fixtures.wrap_embedder(
+                embedder,
+                provider_id(config, config.providers.embedding),
+                embedding_model.clone(),
+            );
+            match config.fixtures.mode {
+                FixtureMode::Replay => {
+                    println!("Replaying model calls from {}", config.fixtures.dir)
+                }
+                _ => println!("Recording model calls to {}", config.fixtures.dir),
+            }
+        }
+
+        // Mock replies must never be served once real calls are enabled, so
+        // the mock is not cached. Neither are fixtures: a cache hit would
+        // keep a call from being recorded.
+        match ResponseCache::new(pool, &config.cache) {
+            Some(cache) if !config.mock_gemini && config.fixtures.mode == FixtureMode::Off => {
+                llm = cache.wrap_llm(
+                    llm,
+                    provider_id(config, config.providers.llm),
+                    default_model.clone(),
+                );
+                embedder = cache.wrap_embedder(
+                    embedder,
+                    provider_id(config, config.providers.embedding),
+                    embedding_model.clone(),
+                );
+                println!("Caching model responses for {}s", config.cache.ttl_secs);
+            }
+            _ => println!("Model response cache disabled"),
+        }
+        let model = |params: &GenerationParams| {
+            params
+                .model
+                .clone()
+                .unwrap_or_else(|| default_model.clone())
+        };
+        println!(
+            "Generation models: extraction={}, exam={}, explanation={}, verification={}",
+            model(&config.generation.extraction),
+            model(&config.generation.exam),
+            model(&config.generation.explanation),
+            model(&config.generation.verification)
+        );
+        Self {
+            llm,
+            embedder,
+            breaker,
+        }
+    }
+}
+
+// Names the backend in cache keys and fixture hashes; OpenAI-compatible servers are told apart
+// by their base URL
+fn provider_id(config: &Config, kind: ProviderKind) -> String {
+    match kind {
+        ProviderKind::Gemini => "gemini".to_string(),
+        ProviderKind::OpenAi => format!("openai:{}", config.providers.openai.base_url),
+    }
+}
@@ -0,0 +1,484 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Answer choices per item. CU-TEP items have four; a few practice books use
+// three or five.
+const MIN_OPTIONS: usize = 2;
+const MAX_OPTIONS: usize = 5;
+
+// The CU-TEP item types
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionType {
+    // Multiple choice about a reading passage
+    ReadingComprehension,
+    // A sentence with underlined segments, one of which is wrong
+    ErrorIdentification,
+    // Multiple choice about a recording (the transcript, when the source has it)
+    Listening,
+    // Cloze: fill the blank in a sentence or passage
+    SentenceCompletion,
+}
+
+impl QuestionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuestionType::ReadingComprehension => "reading_comprehension",
+            QuestionType::ErrorIdentification => "error_identification",
+            QuestionType::Listening => "listening",
+            QuestionType::SentenceCompletion => "sentence_completion",
+        }
+    }
+}
+
+// What a passage is: the text of a reading set, the transcript of a
+// listening set, or a cloze text with blanks
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PassageKind {
+    Reading,
+    Transcript,
+    Cloze,
+}
+
+impl PassageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PassageKind::Reading => "reading",
+            PassageKind::Transcript => "transcript",
+            PassageKind::Cloze => "cloze",
+        }
+    }
+}
+
+// A passage shared by a set of items, as the model writes it
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct PassageDraft {
+    pub kind: PassageKind,
+    // How the source labels the passage, e.g. "Passage 2"
+    pub label: Option<String>,
+    pub text: String,
+}
+
+// An item as the model writes it. Provider response schemas cannot express a
+// tagged union, so every type shares this flat shape; `validate` turns it
+// into a `Question`.
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct QuestionDraft {
+    #[serde(rename = "type")]
+    pub question_type: QuestionType,
+    // The question; for error identification, the whole sentence
+    pub stem: String,
+    // Choices in order (A, B, C, ...); for error identification, the
+    // underlined segments in the order they appear
+    pub options: Vec<String>,
+    // Letter of the correct choice; empty when the source gives no key
+    pub answer: String,
+    pub explanation: Option<String>,
+    // Index of the passage the item belongs to, in the reply's `passages`
+    pub passage: Option<usize>,
+    // Corrected form of the wrong segment (error identification only)
+    pub correction: Option<String>,
+}
+
+// A validated CU-TEP item, stored as `questions.content`. The passage of a
+// set is stored once and linked from its questions (`questions.passage_id`).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Question {
+    ReadingComprehension(MultipleChoice),
+    ErrorIdentification(ErrorIdentification),
+    Listening(MultipleChoice),
+    SentenceCompletion(MultipleChoice),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MultipleChoice {
+    pub stem: String,
+    pub options: Vec<String>,
+    // Missing when the source has no answer key (see core::verification)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<AnswerKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ErrorIdentification {
+    pub sentence: String,
+    // Underlined segments, keyed A, B, C, ... in sentence order
+    pub segments: Vec<String>,
+    // The segment that contains the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<AnswerKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+}
+
+// Letter of a choice: `A` is the first option
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct AnswerKey(u8);
+
+impl AnswerKey {
+    pub fn from_index(index: usize) -> Self {
+        AnswerKey(index as u8)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    // "B", "b", "(B)" and "B." all name the second choice
+    pub fn parse(s: &str) -> Option<Self> {
+        let letter = s
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches([')', '.'])
+            .trim();
+        match letter.as_bytes() {
+            [c] if c.is_ascii_alphabetic() => Some(AnswerKey(c.to_ascii_uppercase() - b'A')),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<String> for AnswerKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        AnswerKey::parse(&s).ok_or_else(|| format!("not an answer key: {}", s))
+    }
+}
+
+impl From<AnswerKey> for String {
+    fn from(key: AnswerKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for AnswerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", (b'A' + self.0) as char)
+    }
+}
+
+impl Question {
+    pub fn question_type(&self) -> QuestionType {
+        match self {
+            Question::ReadingComprehension(_) => QuestionType::ReadingComprehension,
+            Question::ErrorIdentification(_) => QuestionType::ErrorIdentification,
+            Question::Listening(_) => QuestionType::Listening,
+            Question::SentenceCompletion(_) => QuestionType::SentenceCompletion,
+        }
+    }
+
+    // The question, or the sentence of an error identification item
+    pub fn stem(&self) -> &str {
+        match self {
+            Question::ErrorIdentification(q) => &q.sentence,
+            Question::ReadingComprehension(q)
+            | Question::Listening(q)
+            | Question::SentenceCompletion(q) => &q.stem,
+        }
+    }
+
+    // Choices keyed A, B, C, ...
+    pub fn options(&self) -> &[String] {
+        match self {
+            Question::ErrorIdentification(q) => &q.segments,
+            Question::ReadingComprehension(q)
+            | Question::Listening(q)
+            | Question::SentenceCompletion(q) => &q.options,
+        }
+    }
+
+    pub fn answer(&self) -> Option<AnswerKey> {
+        match self {
+            Question::ErrorIdentification(q) => q.answer,
+            Question::ReadingComprehension(q)
+            | Question::Listening(q)
+            | Question::SentenceCompletion(q) => q.answer,
+        }
+    }
+}
+
+impl QuestionDraft {
+    // Check the item against the rules of its type. `passages` are the ones
+    // its `passage` index refers to. Every problem is reported, so a
+    // quarantined item shows all of them at once.
+    pub fn validate(self, passages: &[PassageDraft]) -> Result<Question, Vec<String>> {
+        let mut errors = Vec::new();
+        let stem = self.stem.trim().to_string();
+        let options: Vec<String> = self.options.iter().map(|o| o.trim().to_string()).collect();
+        let explanation = non_empty(self.explanation);
+        let passage = match self.passage {
+            Some(i) if i >= passages.len() => {
+                errors.push(format!("passage: there is no passage {}", i));
+                None
+            }
+            Some(i) if passages[i].text.trim().is_empty() => {
+                errors.push(format!("passage: passage {} has no text", i));
+                None
+            }
+            Some(i) => Some(&passages[i]),
+            None => None,
+        };
+
+        if stem.is_empty() {
+            errors.push("stem: must not be empty".to_string());
+        }
+        if !(MIN_OPTIONS..=MAX_OPTIONS).contains(&options.len()) {
+            errors.push(format!(
+                "options: must have {} to {} choices (got {})",
+                MIN_OPTIONS,
+                MAX_OPTIONS,
+                options.len()
+            ));
+        }
+        if options.iter().any(|o| o.is_empty()) {
+            errors.push("options: must not be empty".to_string());
+        }
+        for (i, option) in options.iter().enumerate() {
+            if !option.is_empty() && options[..i].contains(option) {
+                errors.push(format!("options: {} is repeated", option));
+            }
+        }
+        // No key is not an error: the verification pass flags it
+        let answer = match AnswerKey::parse(&self.answer) {
+            Some(key) if key.index() < options.len() => Some(key),
+            None if self.answer.trim().is_empty() => None,
+            _ => {
+                errors.push(format!(
+                    "answer: must be the letter of one of the {} options (got {})",
+                    options.len(),
+                    self.answer
+                ));
+                None
+            }
+        };
+
+        // Sets only hold items of their own kind
+        let expected = match self.question_type {
+            QuestionType::ReadingComprehension => Some(PassageKind::Reading),
+            QuestionType::Listening => Some(PassageKind::Transcript),
+            QuestionType::SentenceCompletion => Some(PassageKind::Cloze),
+            QuestionType::ErrorIdentification => None,
+        };
+        if let Some(passage) = passage {
+            if Some(passage.kind) != expected {
+                errors.push(format!(
+                    "passage: a {} passage cannot hold {} items",
+                    passage.kind.as_str(),
+                    self.question_type.as_str()
+                ));
+            }
+        }
+
+        match self.question_type {
+            QuestionType::ReadingComprehension => {
+                if self.passage.is_none() {
+                    errors.push("passage: reading comprehension needs a passage".to_string());
+                }
+            }
+            QuestionType::SentenceCompletion => {
+                let has_blank = |text: &str| text.contains("__");
+                if !has_blank(&stem) && !passage.is_some_and(|p| has_blank(&p.text)) {
+                    errors.push("stem: sentence completion needs a blank (___)".to_string());
+                }
+            }
+            QuestionType::ErrorIdentification => {
+                // Segments are underlined parts of the sentence, in order
+                let mut rest = stem.as_str();
+                for option in options.iter().filter(|o| !o.is_empty()) {
+                    match rest.find(option.as_str()) {
+                        Some(at) => rest = &rest[at + option.len()..],
+                        None => errors.push(format!(
+                            "options: {} is not a segment of the sentence (in order)",
+                            option
+                        )),
+                    }
+                }
+            }
+            QuestionType::Listening => {}
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let multiple_choice = || MultipleChoice {
+            stem: stem.clone(),
+            options: options.clone(),
+            answer,
+            explanation: explanation.clone(),
+        };
+        Ok(match self.question_type {
+            QuestionType::ReadingComprehension => Question::ReadingComprehension(multiple_choice()),
+            QuestionType::Listening => Question::Listening(multiple_choice()),
+            QuestionType::SentenceCompletion => Question::SentenceCompletion(multiple_choice()),
+            QuestionType::ErrorIdentification => {
+                Question::ErrorIdentification(ErrorIdentification {
+                    sentence: stem,
+                    segments: options,
+                    answer,
+                    correction: non_empty(self.correction),
+                    explanation,
+                })
+            }
+        })
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn draft(value: serde_json::Value) -> QuestionDraft {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_drafts_become_typed_questions() {
+        let error_id = draft(json!({
+            "type": "error_identification",
+            "stem": "She have lived here since 2010 and likes it.",
+            "options": ["have", "lived", "since", "likes"],
+            "answer": "(a)",
+            "correction": "has"
+        }))
+        .validate(&[])
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&error_id).unwrap(),
+            json!({
+                "type": "error_identification",
+                "sentence": "She have lived here since 2010 and likes it.",
+                "segments": ["have", "lived", "since", "likes"],
+                "answer": "A",
+                "correction": "has"
+            })
+        );
+
+        let cloze = draft(json!({
+            "type": "sentence_completion",
+            "stem": "The report ___ by Friday.",
+            "options": ["is finishing", "will be finished", "finish"],
+            "answer": "B"
+        }))
+        .validate(&[])
+        .unwrap();
+        let Question::SentenceCompletion(cloze) = cloze else {
+            panic!("wrong type: {:?}", cloze);
+        };
+        assert_eq!(
+            cloze.options[cloze.answer.unwrap().index()],
+            "will be finished"
+        );
+        assert_eq!(
+            serde_json::from_value::<Question>(json!({
+                "type": "sentence_completion",
+                "stem": "The report ___ by Friday.",
+                "options": ["is finishing", "will be finished", "finish"],
+                "answer": "B"
+            }))
+            .unwrap(),
+            Question::SentenceCompletion(cloze)
+        );
+
+        // A source without an answer key still gives a question, without one
+        let unkeyed = draft(json!({
+            "type": "listening",
+            "stem": "Where is the speaker?",
+            "options": ["At home", "At work"],
+            "answer": " "
+        }))
+        .validate(&[])
+        .unwrap();
+        assert_eq!(unkeyed.answer(), None);
+        assert!(serde_json::to_value(&unkeyed)
+            .unwrap()
+            .get("answer")
+            .is_none());
+    }
+
+    #[test]
+    fn test_items_that_do_not_fit_are_rejected() {
+        let errors = draft(json!({
+            "type": "reading_comprehension",
+            "stem": "What is the main idea?",
+            "options": ["Cats", "Cats", ""],
+            "answer": "D"
+        }))
+        .validate(&[])
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "options: must not be empty",
+                "options: Cats is repeated",
+                "answer: must be the letter of one of the 3 options (got D)",
+                "passage: reading comprehension needs a passage",
+            ]
+        );
+
+        let errors = draft(json!({
+            "type": "error_identification",
+            "stem": "He go to school every days.",
+            "options": ["every", "go"],
+            "answer": "B"
+        }))
+        .validate(&[])
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            ["options: go is not a segment of the sentence (in order)"]
+        );
+
+        // A cloze item may have its blank in the passage instead
+        let passages = [
+            PassageDraft {
+                kind: PassageKind::Cloze,
+                label: None,
+                text: "Bees are (1) ___ insects.".to_string(),
+            },
+            PassageDraft {
+                kind: PassageKind::Transcript,
+                label: Some("Conversation 1".to_string()),
+                text: "A: Hello.".to_string(),
+            },
+        ];
+        let cloze = |passage| {
+            draft(json!({
+                "type": "sentence_completion",
+                "stem": "Choose the best word for (1).",
+                "options": ["busy", "lazy"],
+                "answer": "A",
+                "passage": passage
+            }))
+            .validate(&passages)
+        };
+        assert!(cloze(json!(0)).is_ok());
+        assert_eq!(
+            cloze(json!(1)).unwrap_err(),
+            [
+                "passage: a transcript passage cannot hold sentence_completion items",
+                "stem: sentence completion needs a blank (___)",
+            ]
+        );
+        assert_eq!(
+            cloze(json!(2)).unwrap_err()[0],
+            "passage: there is no passage 2"
+        );
+    }
+}
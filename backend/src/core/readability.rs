@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+// Length and difficulty of a passage, measured locally so passages can be
+// matched to a level without another model call
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Readability {
+    pub word_count: i32,
+    pub sentence_count: i32,
+    // Flesch Reading Ease: roughly 0 (academic) to 100 (very easy);
+    // CU-TEP passages are usually between 30 and 60
+    pub flesch_reading_ease: f64,
+}
+
+pub fn measure(text: &str) -> Readability {
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| w.chars().any(|c| c.is_alphabetic()))
+        .collect();
+    let sentences = text
+        .split(['.', '!', '?'])
+        .filter(|s| s.chars().any(|c| c.is_alphabetic()))
+        .count()
+        .max(1);
+    let syllables: usize = words.iter().map(|w| syllables(w)).sum();
+
+    let flesch_reading_ease = if words.is_empty() {
+        0.0
+    } else {
+        let words_per_sentence = words.len() as f64 / sentences as f64;
+        let syllables_per_word = syllables as f64 / words.len() as f64;
+        206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word
+    };
+    Readability {
+        word_count: words.len() as i32,
+        sentence_count: sentences as i32,
+        flesch_reading_ease: (flesch_reading_ease * 10.0).round() / 10.0,
+    }
+}
+
+// Vowel groups, not counting a silent final "e"; every word has at least one
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_text_reads_easier_than_academic_text() {
+        let simple = measure("The cat sat on the mat. It was a big cat! Was it red?");
+        assert_eq!(simple.word_count, 14);
+        assert_eq!(simple.sentence_count, 3);
+
+        let academic = measure(
+            "Photosynthetic organisms convert electromagnetic radiation into chemical energy, \
+            facilitating the biological processes underlying terrestrial ecosystems.",
+        );
+        assert_eq!(academic.sentence_count, 1);
+        assert!(simple.flesch_reading_ease > 90.0);
+        assert!(academic.flesch_reading_ease < 10.0);
+        assert_eq!(syllables("table"), 2);
+        assert_eq!(syllables("make"), 1);
+    }
+}
@@ -0,0 +1,607 @@
+use crate::core::config::{ResilienceConfig, Secret};
+use crate::core::key_pool::KeyPool;
+use crate::core::llm_error::LlmError;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client, RequestBuilder, Response, StatusCode};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Longest Retry-After we are willing to sleep for inside a single job
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+// HTTP client for model APIs: every request waits for rate-limit capacity,
+// is bounded by a timeout, and is retried on 429/5xx/network errors with
+// jittered exponential backoff. Repeated failures open the shared breaker.
+#[derive(Clone)]
+pub struct ResilientClient {
+    client: Client,
+    config: ResilienceConfig,
+    limiter: Arc<RateLimiter>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl ResilientClient {
+    pub fn new(config: &ResilienceConfig, breaker: Arc<CircuitBreaker>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+        Self {
+            client,
+            config: config.clone(),
+            limiter: Arc::new(RateLimiter::new(
+                config.requests_per_minute,
+                config.tokens_per_minute,
+            )),
+            breaker,
+        }
+    }
+
+    // `build` is called once per attempt; `estimated_tokens` is charged
+    // against the tokens-per-minute budget. Only successful responses are
+    // returned, anything else becomes an `LlmError`.
+    pub async fn send<F>(&self, estimated_tokens: u32, build: F) -> Result<Response, LlmError>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        self.send_inner(estimated_tokens, None, |client, _| build(client))
+            .await
+    }
+
+    // Like `send`, but every attempt takes the next usable key from `keys`.
+    // A key that hits its quota (RESOURCE_EXHAUSTED) is quarantined and the
+    // request moves straight on to another key. When every key is
+    // quarantined, the attempt waits for the first one to come back.
+    pub async fn send_with_key<F>(
+        &self,
+        keys: &KeyPool,
+        estimated_tokens: u32,
+        build: F,
+    ) -> Result<Response, LlmError>
+    where
+        F: Fn(&Client, &Secret) -> RequestBuilder,
+    {
+        self.send_inner(estimated_tokens, Some(keys), |client, key| {
+            build(client, key.expect("a key is acquired for every attempt"))
+        })
+        .await
+    }
+
+    async fn send_inner<F>(
+        &self,
+        estimated_tokens: u32,
+        keys: Option<&KeyPool>,
+        build: F,
+    ) -> Result<Response, LlmError>
+    where
+        F: Fn(&Client, Option<&Secret>) -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        let mut key_switches = 0;
+        loop {
+            // Held until this attempt is over; after the cooldown, only the
+            // holder of the trial permit gets through
+            let permit = match self.breaker.allow() {
+                Ok(permit) => permit,
+                Err(retry_in) => return Err(LlmError::Unavailable { retry_in }),
+            };
+
+            let key = match keys.map(|pool| pool.acquire()).transpose() {
+                Ok(key) => key,
+                Err(retry_in) => {
+                    attempt += 1;
+                    if attempt > self.config.max_retries {
+                        return Err(LlmError::RateLimited {
+                            code: None,
+                            message: "Every API key is quarantined after hitting its quota"
+                                .to_string(),
+                            retry_after: Some(retry_in),
+                            body: String::new(),
+                        });
+                    }
+                    let delay = retry_in.min(MAX_RETRY_AFTER);
+                    drop(permit);
+                    eprintln!(
+                        "Every API key is quarantined, retrying in {}ms",
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+
+            self.limiter.acquire(estimated_tokens).await;
+
+            let error = match build(&self.client, key.as_ref().map(|k| &k.key))
+                .send()
+                .await
+            {
+                Ok(res) if res.status().is_success() => {
+                    self.breaker.record_success();
+                    return Ok(res);
+                }
+                Ok(res) => {
+                    let status = res.status();
+                    let retry_after = parse_retry_after(&res);
+                    let body = res.text().await.unwrap_or_default();
+                    let error = LlmError::from_response(status.as_u16(), body, retry_after);
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => LlmError::Network {
+                    message: e.to_string(),
+                },
+                Err(e) => {
+                    return Err(LlmError::Network {
+                        message: e.to_string(),
+                    })
+                }
+            };
+
+            // Another key may still have quota: switch without backing off.
+            // A plain 429 (a burst over a rate limit) is only backed off, as
+            // the key itself is not out of quota.
+            if let (Some(pool), Some(key), Some(retry_after)) =
+                (keys, &key, quota_exhausted(&error))
+            {
+                pool.quarantine(key, retry_after);
+                if pool.has_available() && key_switches < pool.key_count() {
+                    key_switches += 1;
+                    continue;
+                }
+            }
+
+            self.breaker.record_failure();
+            drop(permit);
+            attempt += 1;
+            if attempt > self.config.max_retries {
+                eprintln!("Provider request failed after {} attempts", attempt);
+                return Err(error);
+            }
+
+            let delay = error
+                .retry_after()
+                .map(|d| d.min(MAX_RETRY_AFTER))
+                .unwrap_or_else(|| backoff_delay(&self.config, attempt));
+            eprintln!(
+                "Provider request failed ({}), retrying in {}ms",
+                error,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+// `Some(retry_after)` when the error says the key ran out of quota
+fn quota_exhausted(error: &LlmError) -> Option<Option<Duration>> {
+    match error {
+        LlmError::RateLimited {
+            code: Some(code),
+            retry_after,
+            ..
+        } if code == "RESOURCE_EXHAUSTED" => Some(*retry_after),
+        _ => None,
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+// Exponential backoff with "equal jitter": half the step is fixed, half random
+fn backoff_delay(config: &ResilienceConfig, attempt: u32) -> Duration {
+    let step = config
+        .retry_base_ms
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(20))
+        .min(config.retry_max_ms);
+    let half = step / 2;
+    let jitter = if half > 0 {
+        rand::thread_rng().gen_range(0..=half)
+    } else {
+        0
+    };
+    Duration::from_millis(step - half + jitter)
+}
+
+// Retry-After is either delay-seconds or an HTTP date
+fn parse_retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+// Token bucket for requests and tokens per minute. Both buckets start full
+// and refill continuously.
+pub struct RateLimiter {
+    requests_per_minute: u32,
+    tokens_per_minute: u32,
+    buckets: tokio::sync::Mutex<Buckets>,
+}
+
+struct Buckets {
+    requests: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute,
+            tokens_per_minute,
+            buckets: tokio::sync::Mutex::new(Buckets {
+                requests: requests_per_minute as f64,
+                tokens: tokens_per_minute as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    // Wait until one request and `tokens` tokens are available, then take them.
+    // A request larger than the whole per-minute budget waits for a full bucket.
+    pub async fn acquire(&self, tokens: u32) {
+        let rpm = self.requests_per_minute as f64;
+        let tpm = self.tokens_per_minute as f64;
+        let needed_tokens = (tokens as f64).min(tpm);
+
+        loop {
+            let wait = {
+                // Refill for the time since the last check
+                let mut buckets = self.buckets.lock().await;
+                let elapsed = buckets.refilled_at.elapsed().as_secs_f64();
+                buckets.requests = (buckets.requests + elapsed * rpm / 60.0).min(rpm);
+                buckets.tokens = (buckets.tokens + elapsed * tpm / 60.0).min(tpm);
+                buckets.refilled_at = Instant::now();
+
+                let request_wait = if rpm > 0.0 && buckets.requests < 1.0 {
+                    (1.0 - buckets.requests) * 60.0 / rpm
+                } else {
+                    0.0
+                };
+                let token_wait = if tpm > 0.0 && buckets.tokens < needed_tokens {
+                    (needed_tokens - buckets.tokens) * 60.0 / tpm
+                } else {
+                    0.0
+                };
+
+                if request_wait == 0.0 && token_wait == 0.0 {
+                    if rpm > 0.0 {
+                        buckets.requests -= 1.0;
+                    }
+                    if tpm > 0.0 {
+                        buckets.tokens -= needed_tokens;
+                    }
+                    return;
+                }
+                request_wait.max(token_wait)
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+// Opens after `threshold` consecutive failed provider calls and stays open
+// for the cooldown. Then it is half-open: exactly one call is let through as
+// a trial, and the others wait until it ends. Success closes the breaker,
+// another failure re-opens it.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+// How long calls wait for a trial call to end before asking again
+const TRIAL_WAIT: Duration = Duration::from_secs(1);
+
+// Lets a call through the breaker. A trial that ends without a recorded
+// success or failure (a rejected request, a dropped call) frees the trial
+// slot for the next call.
+pub struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.trial {
+            self.breaker.state.lock().unwrap().trial_in_flight = false;
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &ResilienceConfig) -> Self {
+        Self {
+            threshold: config.breaker_threshold,
+            cooldown: Duration::from_secs(config.breaker_cooldown_secs),
+            state: Mutex::new(BreakerState {
+                consecutive_failures: 0,
+                open_until: None,
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    // Time left until the breaker lets calls through again, if it is open or
+    // waiting for a trial call
+    pub fn open_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        Self::wait_time(&state)
+    }
+
+    // A permit for one call, or how long to wait for one. The first call
+    // after the cooldown becomes the trial.
+    pub fn allow(&self) -> Result<BreakerPermit<'_>, Duration> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(wait) = Self::wait_time(&state) {
+            return Err(wait);
+        }
+        let trial = state.open_until.is_some();
+        state.trial_in_flight = trial;
+        Ok(BreakerPermit {
+            breaker: self,
+            trial,
+        })
+    }
+
+    fn wait_time(state: &BreakerState) -> Option<Duration> {
+        let until = state.open_until?;
+        match until.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => Some(remaining),
+            _ if state.trial_in_flight => Some(TRIAL_WAIT),
+            _ => None,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+        state.trial_in_flight = false;
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.threshold {
+            let was_open = state.open_until.is_some_and(|until| until > Instant::now());
+            state.open_until = Some(Instant::now() + self.cooldown);
+            if !was_open {
+                eprintln!(
+                    "Circuit breaker opened for {}s after {} consecutive provider failures",
+                    self.cooldown.as_secs(),
+                    state.consecutive_failures
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        http::{HeaderMap, StatusCode as AxumStatus},
+        routing::get,
+        Router,
+    };
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn test_config() -> ResilienceConfig {
+        ResilienceConfig {
+            retry_base_ms: 10,
+            retry_max_ms: 50,
+            breaker_threshold: 3,
+            ..ResilienceConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_bounded_and_grows() {
+        let config = ResilienceConfig {
+            retry_base_ms: 100,
+            retry_max_ms: 1000,
+            ..ResilienceConfig::default()
+        };
+        for _ in 0..20 {
+            let first = backoff_delay(&config, 1).as_millis();
+            assert!((50..=100).contains(&first), "{}", first);
+            let third = backoff_delay(&config, 3).as_millis();
+            assert!((200..=400).contains(&third), "{}", third);
+            let capped = backoff_delay(&config, 30).as_millis();
+            assert!((500..=1000).contains(&capped), "{}", capped);
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(&test_config());
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.open_for().is_none());
+        breaker.record_failure();
+        assert!(breaker.open_for().is_some());
+        breaker.record_success();
+        assert!(breaker.open_for().is_none());
+    }
+
+    #[test]
+    fn test_half_open_breaker_lets_one_trial_through() {
+        let config = ResilienceConfig {
+            breaker_cooldown_secs: 0,
+            ..test_config()
+        };
+        let breaker = CircuitBreaker::new(&config);
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+
+        // The cooldown is over: one trial, the others wait for it
+        let trial = breaker.allow().expect("the trial should be let through");
+        assert!(breaker.allow().is_err());
+        assert!(breaker.open_for().is_some());
+
+        // A failed trial re-opens the breaker for another trial
+        breaker.record_failure();
+        drop(trial);
+        let trial = breaker
+            .allow()
+            .expect("the next trial should be let through");
+        assert!(breaker.allow().is_err());
+
+        // A trial that ends without an outcome frees the slot
+        drop(trial);
+        let trial = breaker.allow().expect("the slot should be free again");
+
+        // A successful one closes the breaker
+        breaker.record_success();
+        drop(trial);
+        let first = breaker.allow();
+        let second = breaker.allow();
+        assert!(first.is_ok() && second.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_waits_for_tokens() {
+        // 600 tokens/minute = 10 tokens/second
+        let limiter = RateLimiter::new(0, 600);
+        let start = Instant::now();
+        limiter.acquire(600).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        limiter.acquire(5).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success_honoring_retry_after() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                let counter = counter.clone();
+                async move {
+                    let mut headers = HeaderMap::new();
+                    if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                        headers.insert("retry-after", "0".parse().unwrap());
+                        return (AxumStatus::SERVICE_UNAVAILABLE, headers, "busy");
+                    }
+                    (AxumStatus::OK, headers, "ok")
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let breaker = Arc::new(CircuitBreaker::new(&test_config()));
+        let client = ResilientClient::new(&test_config(), breaker.clone());
+        let res = client
+            .send(10, |c| c.get(&url))
+            .await
+            .expect("request should succeed after retries");
+        assert_eq!(res.text().await.unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(breaker.open_for().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_quota_error_moves_to_next_key() {
+        let app = Router::new().route(
+            "/",
+            get(|headers: HeaderMap| async move {
+                if headers["x-goog-api-key"] == "exhausted" {
+                    return (AxumStatus::TOO_MANY_REQUESTS, QUOTA_EXHAUSTED);
+                }
+                (AxumStatus::OK, "ok")
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let keys = KeyPool::new(
+            vec![Secret::new("exhausted"), Secret::new("fresh")],
+            Duration::from_secs(60),
+        );
+        let breaker = Arc::new(CircuitBreaker::new(&test_config()));
+        let client = ResilientClient::new(&test_config(), breaker.clone());
+        for _ in 0..3 {
+            let res = client
+                .send_with_key(&keys, 10, |c, key| {
+                    c.get(&url).header("x-goog-api-key", key.expose())
+                })
+                .await
+                .expect("the fresh key should be used");
+            assert_eq!(res.text().await.unwrap(), "ok");
+        }
+        assert!(breaker.open_for().is_none());
+    }
+
+    const QUOTA_EXHAUSTED: &str =
+        r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#;
+
+    #[tokio::test]
+    async fn test_single_key_retries_after_429() {
+        // Each call gets a 429 without Retry-After, then a 200
+        let hits = Arc::new(AtomicU32::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/",
+            get(move |headers: HeaderMap| {
+                let counter = counter.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) % 2 == 1 {
+                        return (AxumStatus::OK, "ok");
+                    }
+                    if headers["x-goog-api-key"] == "burst" {
+                        return (AxumStatus::TOO_MANY_REQUESTS, "slow down");
+                    }
+                    (AxumStatus::TOO_MANY_REQUESTS, QUOTA_EXHAUSTED)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let breaker = Arc::new(CircuitBreaker::new(&test_config()));
+        let client = ResilientClient::new(&test_config(), breaker);
+        // A plain 429 is backed off; an exhausted quota quarantines the only
+        // key, and the next attempt waits for it
+        for name in ["burst", "exhausted"] {
+            let keys = KeyPool::new(vec![Secret::new(name)], Duration::from_millis(100));
+            let start = Instant::now();
+            let res = client
+                .send_with_key(&keys, 10, |c, key| {
+                    c.get(&url).header("x-goog-api-key", key.expose())
+                })
+                .await
+                .expect("the retry should succeed");
+            assert_eq!(res.text().await.unwrap(), "ok");
+            if name == "exhausted" {
+                assert!(start.elapsed() >= Duration::from_millis(100));
+            }
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::core::llm_error::LlmError;
+use crate::core::traits::{ChunkStream, GenerationChunk, TokenUsage};
+use futures_util::{stream, StreamExt};
+use std::future::Future;
+
+// Pass `chunks` through unchanged and, once the stream ends without an
+// error, call `on_complete` with the whole text and the reported usage.
+// Used by the provider decorators that need the full reply (cache,
+// fixtures).
+pub fn on_complete<F, Fut>(chunks: ChunkStream, on_complete: F) -> ChunkStream
+where
+    F: FnOnce(String, Option<TokenUsage>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let state = (chunks, String::new(), None, Some(on_complete));
+    stream::unfold(
+        state,
+        |(mut chunks, mut text, mut usage, mut callback)| async move {
+            match chunks.next().await {
+                Some(Ok(chunk)) => {
+                    text.push_str(&chunk.text);
+                    usage = chunk.usage.or(usage);
+                    Some((Ok(chunk), (chunks, text, usage, callback)))
+                }
+                // A failed reply is never handed to the callback
+                Some(Err(e)) => Some((Err(e), (chunks, text, usage, None))),
+                None => {
+                    if let Some(callback) = callback.take() {
+                        callback(text, usage).await;
+                    }
+                    None
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+// A whole reply as a one-chunk stream
+pub fn single_chunk(text: String, usage: Option<TokenUsage>) -> ChunkStream {
+    stream::once(async move { Ok::<_, LlmError>(GenerationChunk { text, usage }) }).boxed()
+}
+
+// Splits a `text/event-stream` body into the `data` of each event. Bytes
+// arrive in arbitrary pieces, so incomplete events are kept until the blank
+// line that ends them.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some((end, separator)) = find_event_end(&self.buffer) {
+            let event: Vec<u8> = self.buffer.drain(..end + separator).collect();
+            let event = String::from_utf8_lossy(&event[..end]);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+// Position and length of the first blank line (`\n\n` or `\r\n\r\n`)
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let lf = buffer.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+    let crlf = buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| (i, 4));
+    match (lf, crlf) {
+        (Some(a), Some(b)) => Some(if a.0 < b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+// Pulls complete items out of the arrays of a JSON object while the object
+// is still being written, e.g. each question of
+// `{"passages": [...], "questions": [{...}, {...}` as soon as its closing
+// brace arrives, together with the key of its array.
+#[derive(Default)]
+pub struct ArrayItems {
+    text: String,
+    // Scan position in `text`
+    pos: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // Bounds of the last string directly in the object: the key of an array
+    // that starts next
+    string_start: usize,
+    last_string: Option<(usize, usize)>,
+    // Key of the array being read
+    key: Option<String>,
+    item_start: Option<usize>,
+}
+
+// Depth of the object's arrays (the object itself is at depth 1)
+const ARRAY_DEPTH: usize = 2;
+
+impl ArrayItems {
+    // Complete items found in the text so far, as (array key, JSON text)
+    pub fn push(&mut self, chunk: &str) -> Vec<(String, String)> {
+        self.text.push_str(chunk);
+        let mut items = Vec::new();
+        while self.pos < self.text.len() {
+            let c = self.text.as_bytes()[self.pos];
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        if self.depth == 1 {
+                            self.last_string = Some((self.string_start + 1, self.pos));
+                        }
+                    }
+                    _ => {}
+                }
+            } else {
+                match c {
+                    b'"' => {
+                        self.in_string = true;
+                        self.string_start = self.pos;
+                    }
+                    b'{' | b'[' => {
+                        if self.depth == 1 && c == b'[' {
+                            self.key = self
+                                .last_string
+                                .map(|(start, end)| self.text[start..end].to_string());
+                        } else if self.depth == ARRAY_DEPTH && self.key.is_some() {
+                            self.item_start.get_or_insert(self.pos);
+                        }
+                        self.depth += 1;
+                    }
+                    b'}' | b']' => {
+                        self.depth = self.depth.saturating_sub(1);
+                        if self.depth == 1 {
+                            self.key = None;
+                        } else if self.depth == ARRAY_DEPTH {
+                            if let (Some(key), Some(start)) = (&self.key, self.item_start.take()) {
+                                items.push((key.clone(), self.text[start..=self.pos].to_string()));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+        }
+        items
+    }
+
+    // Everything received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_events_survive_arbitrary_splits() {
+        let body = "data: {\"a\":1}\n\n: keep-alive\n\ndata: {\"b\":\r\ndata: 2}\r\n\r\ndata: tail";
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for piece in body.as_bytes().chunks(5) {
+            events.extend(decoder.push(piece));
+        }
+        assert_eq!(events, ["{\"a\":1}", "{\"b\":\n2}"]);
+    }
+
+    #[test]
+    fn test_array_items_are_emitted_when_complete() {
+        let reply = r#"{"passages": [{"text": "A [short] one"}], "count": 2,
+            "questions": [{"question": "Pick {one}", "options": ["a", "b\"]"]},
+            {"question": "Next", "options": []}], "note": "[{x}]"}"#;
+        let mut items = ArrayItems::default();
+        let mut found = Vec::new();
+        let mut complete_after = Vec::new();
+        for (i, piece) in reply.as_bytes().chunks(7).enumerate() {
+            let piece = std::str::from_utf8(piece).unwrap();
+            for (key, item) in items.push(piece) {
+                found.push(format!("{}: {}", key, item));
+                complete_after.push(i);
+            }
+        }
+        assert_eq!(
+            found,
+            [
+                r#"passages: {"text": "A [short] one"}"#,
+                r#"questions: {"question": "Pick {one}", "options": ["a", "b\"]"]}"#,
+                r#"questions: {"question": "Next", "options": []}"#
+            ]
+        );
+        // The first item is emitted long before the reply ends
+        assert!(complete_after[0] < reply.len() / 7 / 2);
+        assert_eq!(items.text(), reply);
+    }
+}
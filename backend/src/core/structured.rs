@@ -0,0 +1,260 @@
+use crate::core::config::GenerationParams;
+use crate::core::llm_error::LlmError;
+use crate::core::traits::LlmProvider;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+// How many times a reply that fails validation is sent back for repair
+const MAX_REPAIR_ATTEMPTS: u32 = 2;
+
+// Generate a reply of type `T`: the schema derived from `T` is sent to the
+// provider, the reply is validated against it, and an invalid reply is sent
+// back with the validation errors for a bounded number of repair attempts.
+pub async fn generate_structured<T>(
+    llm: &dyn LlmProvider,
+    prompt: &str,
+    params: &GenerationParams,
+) -> Result<T, LlmError>
+where
+    T: JsonSchema + DeserializeOwned,
+{
+    let schema = schema_for::<T>();
+    let mut reply = llm.generate_json(prompt, Some(&schema), params).await?.text;
+
+    let mut attempt = 0;
+    loop {
+        let errors = match parse_and_validate::<T>(&reply, &schema) {
+            Ok(value) => return Ok(value),
+            Err(errors) => errors,
+        };
+
+        if attempt >= MAX_REPAIR_ATTEMPTS {
+            return Err(LlmError::malformed(
+                format!(
+                    "reply does not match schema after {} repair attempts: {}",
+                    attempt,
+                    errors.join("; ")
+                ),
+                reply,
+            ));
+        }
+        attempt += 1;
+        eprintln!(
+            "Structured reply failed validation ({}), requesting repair {}/{}",
+            errors.join("; "),
+            attempt,
+            MAX_REPAIR_ATTEMPTS
+        );
+
+        let repair_prompt = format!(
+            "Your previous reply did not match the required JSON schema.\n\
+            Validation errors:\n- {}\n\n\
+            Previous reply:\n{}\n\n\
+            Original request:\n{}\n\n\
+            Return the corrected JSON only.",
+            errors.join("\n- "),
+            reply,
+            prompt
+        );
+        reply = llm
+            .generate_json(&repair_prompt, Some(&schema), params)
+            .await?
+            .text;
+    }
+}
+
+// JSON Schema for `T` with every definition inlined (no `$ref`), which is
+// what provider schema parameters accept
+pub fn schema_for<T: JsonSchema>() -> Value {
+    let settings = SchemaSettings::draft07().with(|s| {
+        s.inline_subschemas = true;
+        s.meta_schema = None;
+    });
+    let schema = settings.into_generator().into_root_schema_for::<T>();
+    serde_json::to_value(schema).expect("schema serializes to JSON")
+}
+
+pub fn parse_and_validate<T: DeserializeOwned>(
+    reply: &str,
+    schema: &Value,
+) -> Result<T, Vec<String>> {
+    let value: Value = serde_json::from_str(strip_code_fences(reply))
+        .map_err(|e| vec![format!("reply is not valid JSON: {}", e)])?;
+
+    let mut errors = Vec::new();
+    validate(schema, &value, "$", &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    serde_json::from_value(value).map_err(|e| vec![e.to_string()])
+}
+
+// Models sometimes wrap JSON in ```json ... ``` despite being asked not to
+fn strip_code_fences(reply: &str) -> &str {
+    reply
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim()
+}
+
+// Checks the subset of JSON Schema that derived schemas use: type, enum,
+// required, properties and items
+fn validate(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(t, value)) {
+            errors.push(format!("{} must be of type {}", path, types.join(" or ")));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            errors.push(format!("{} must be one of {}", path, allowed.join(", ")));
+        }
+    }
+
+    if let Value::Object(map) = value {
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for field in required.iter().filter_map(|f| f.as_str()) {
+                if map.get(field).is_none_or(|v| v.is_null()) {
+                    errors.push(format!("{}.{} is required", path, field));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, property) in properties {
+                if let Some(child) = map.get(name) {
+                    validate(property, child, &format!("{}.{}", path, name), errors);
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(item_schema, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::traits::{Generation, TokenUsage};
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    #[derive(Deserialize, JsonSchema, Debug, PartialEq)]
+    struct Answer {
+        choice: Choice,
+        reasons: Vec<String>,
+    }
+
+    #[derive(Deserialize, JsonSchema, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Choice {
+        A,
+        B,
+    }
+
+    // Replays canned replies and records the prompts it was sent
+    struct Scripted {
+        replies: Mutex<Vec<&'static str>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Scripted {
+        async fn generate_text(
+            &self,
+            _prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!()
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            assert!(schema.is_some());
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok(Generation {
+                text: self.replies.lock().unwrap().remove(0).to_string(),
+                model: "scripted".to_string(),
+                usage: TokenUsage::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_reply_is_repaired() {
+        let llm = Scripted {
+            replies: Mutex::new(vec![
+                r#"```json
+                {"choice": "c"}
+                ```"#,
+                r#"{"choice": "b", "reasons": ["grammar"]}"#,
+            ]),
+            prompts: Mutex::new(Vec::new()),
+        };
+
+        let answer: Answer = generate_structured(&llm, "Pick one", &GenerationParams::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            answer,
+            Answer {
+                choice: Choice::B,
+                reasons: vec!["grammar".to_string()]
+            }
+        );
+
+        let prompts = llm.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains(r#"$.choice must be one of "a", "b""#));
+        assert!(prompts[1].contains("$.reasons is required"));
+    }
+
+    #[tokio::test]
+    async fn test_repairs_are_bounded() {
+        let llm = Scripted {
+            replies: Mutex::new(vec!["not json", "[]", "{}"]),
+            prompts: Mutex::new(Vec::new()),
+        };
+
+        let result =
+            generate_structured::<Answer>(&llm, "Pick one", &GenerationParams::default()).await;
+        assert!(matches!(result, Err(LlmError::MalformedResponse { .. })));
+        assert_eq!(llm.prompts.lock().unwrap().len(), 3);
+    }
+}
@@ -1,17 +1,28 @@
+use crate::core::config::GenerationParams;
+use crate::core::llm_error::LlmError;
+use crate::core::streaming;
 use async_trait::async_trait;
+use futures_util::stream::BoxStream;
 use serde_json::Value;
 
-// Domain Models
-#[derive(Debug, Clone)]
-pub struct ExamRequest {
-    pub user_id: String,
-    // Add other fields as needed
+// A piece of a streamed exam. A passage comes before the questions that
+// refer to it.
+pub enum ExamItem {
+    Passage(Value),
+    Question(Value),
+}
+
+// Passages and questions of an exam delivered as the model writes them
+pub struct ExamStream {
+    pub prompt_version: String,
+    pub items: BoxStream<'static, Result<ExamItem, LlmError>>,
 }
 
 // Volatile: How exams are generated changes (e.g. Prompt tuning, different models)
 #[async_trait]
 pub trait ExamGenerationEngine: Send + Sync {
-    async fn generate_exam(&self, topic: &str, difficulty: &str) -> Result<Value, String>;
+    // Each passage and question is yielded as soon as it is complete
+    async fn stream_exam(&self, topic: &str, difficulty: &str) -> Result<ExamStream, LlmError>;
 }
 
 // Volatile: How we personalize changes (e.g. Simple Random vs ML model)
@@ -23,9 +34,112 @@ pub trait PersonalizationEngine: Send + Sync {
 // Stable/Accessor: Wrapper around Vector DB details
 #[async_trait]
 pub trait VectorAccessor: Send + Sync {
+    // Questions whose answer key was verified with less than `min_confidence`
+    // are left out; questions not verified yet (verification off, failed or
+    // still pending) are kept. Each comes with its `id`,
+    // `verification_status` and `answer_confidence`.
     async fn find_similar_questions(
         &self,
         vector: &[f32],
         limit: i64,
+        min_confidence: f64,
     ) -> Result<Vec<Value>, String>;
 }
+
+// Tokens a provider billed for one call. Providers that do not report usage
+// get an estimate from the text length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+// A model reply and what it cost
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub text: String,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+// Vectors in input order and what they cost (prompt tokens only)
+#[derive(Debug, Clone)]
+pub struct Embeddings {
+    pub vectors: Vec<Vec<f32>>,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+// A piece of a streamed reply. The provider's usage comes with the last piece.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationChunk {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+
+pub type ChunkStream = BoxStream<'static, Result<GenerationChunk, LlmError>>;
+
+// A reply delivered in pieces as the model writes it
+pub struct GenerationStream {
+    pub model: String,
+    pub chunks: ChunkStream,
+}
+
+// Volatile: Which model writes text (Gemini today, other providers tomorrow)
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // `params` carries the model and sampling settings of the calling use case
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError>;
+
+    // Returns the raw JSON text. `schema` is a JSON Schema the response should
+    // follow; providers without schema support only request JSON output.
+    async fn generate_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError>;
+
+    // `generate_json`, streamed. Errors before the first piece (rejected
+    // request, open breaker) are returned directly. Providers without
+    // streaming send the whole reply as one piece.
+    async fn stream_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<GenerationStream, LlmError> {
+        let generation = self.generate_json(prompt, schema, params).await?;
+        Ok(GenerationStream {
+            model: generation.model,
+            chunks: streaming::single_chunk(generation.text, Some(generation.usage)),
+        })
+    }
+}
+
+// What an embedding will be used for; providers that support it tune the
+// vector for the side of a retrieval it is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingTask {
+    // Stored content that will be searched (questions, passages)
+    RetrievalDocument,
+    // A search query matched against stored documents
+    RetrievalQuery,
+}
+
+// Volatile: Which model turns text into vectors
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    // One vector per text, in input order. `dimensions` asks models that
+    // support it to shorten their output.
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError>;
+}
@@ -0,0 +1,618 @@
+use crate::core::chunker::estimate_tokens;
+use crate::core::config::{GenerationParams, ModelPrice, UsageConfig};
+use crate::core::llm_error::LlmError;
+use crate::core::traits::{
+    EmbeddingProvider, EmbeddingTask, Embeddings, Generation, GenerationStream, LlmProvider,
+    TokenUsage,
+};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// What a model call was made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Extraction,
+    ExamGeneration,
+    // Answer explanations are not generated yet; their calls will be
+    // recorded under this feature
+    #[allow(dead_code)]
+    Explanation,
+    Verification,
+    Embedding,
+}
+
+impl Feature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Extraction => "extraction",
+            Feature::ExamGeneration => "exam_generation",
+            Feature::Explanation => "explanation",
+            Feature::Verification => "verification",
+            Feature::Embedding => "embedding",
+        }
+    }
+}
+
+// What a call is billed to
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageScope {
+    pub raw_material_id: Option<Uuid>,
+    pub job_id: Option<Uuid>,
+    pub exam_id: Option<Uuid>,
+}
+
+// Writes one `llm_usage` row per provider call
+#[derive(Clone)]
+pub struct UsageRecorder {
+    pool: PgPool,
+    prices: Arc<HashMap<String, ModelPrice>>,
+}
+
+impl UsageRecorder {
+    pub fn new(pool: PgPool, config: &UsageConfig) -> Self {
+        Self {
+            pool,
+            prices: Arc::new(config.prices.clone()),
+        }
+    }
+
+    // A generation provider whose calls are recorded against `scope`
+    pub fn meter_llm(
+        &self,
+        inner: Arc<dyn LlmProvider>,
+        scope: UsageScope,
+        feature: Feature,
+    ) -> Arc<dyn LlmProvider> {
+        Arc::new(MeteredLlm {
+            inner,
+            recorder: self.clone(),
+            scope,
+            feature,
+        })
+    }
+
+    // An embedding provider whose calls are recorded against `scope`
+    pub fn meter_embedder(
+        &self,
+        inner: Arc<dyn EmbeddingProvider>,
+        scope: UsageScope,
+    ) -> Arc<dyn EmbeddingProvider> {
+        Arc::new(MeteredEmbedder {
+            inner,
+            recorder: self.clone(),
+            scope,
+        })
+    }
+
+    // Accounting must never fail the call it accounts for, so errors are
+    // only logged. Calls that billed nothing (cache hits) are not recorded.
+    async fn record(&self, scope: UsageScope, feature: Feature, model: &str, usage: TokenUsage) {
+        if usage == TokenUsage::default() {
+            return;
+        }
+        let (prompt_tokens, embedding_tokens) = match feature {
+            Feature::Embedding => (0, usage.prompt_tokens),
+            _ => (usage.prompt_tokens, 0),
+        };
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO llm_usage
+                (feature, model, prompt_tokens, completion_tokens, embedding_tokens, estimated_cost_usd, raw_material_id, job_id, exam_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            feature.as_str(),
+            model,
+            prompt_tokens as i32,
+            usage.completion_tokens as i32,
+            embedding_tokens as i32,
+            estimate_cost(&self.prices, model, usage),
+            scope.raw_material_id,
+            scope.job_id,
+            scope.exam_id
+        )
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            eprintln!("Failed to record LLM usage: {}", e);
+        }
+    }
+}
+
+fn estimate_cost(prices: &HashMap<String, ModelPrice>, model: &str, usage: TokenUsage) -> f64 {
+    price_for(prices, model).map_or(0.0, |price| {
+        (usage.prompt_tokens as f64 * price.input_per_million
+            + usage.completion_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    })
+}
+
+// Exact model name first, then the longest configured prefix, so
+// `gemini-1.5-pro-002` is billed as `gemini-1.5-pro`
+fn price_for(prices: &HashMap<String, ModelPrice>, model: &str) -> Option<ModelPrice> {
+    let model = model.trim_start_matches("models/");
+    prices.get(model).copied().or_else(|| {
+        prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    })
+}
+
+struct MeteredLlm {
+    inner: Arc<dyn LlmProvider>,
+    recorder: UsageRecorder,
+    scope: UsageScope,
+    feature: Feature,
+}
+
+impl MeteredLlm {
+    async fn recorded(&self, result: Result<Generation, LlmError>) -> Result<Generation, LlmError> {
+        if let Ok(generation) = &result {
+            self.recorder
+                .record(
+                    self.scope,
+                    self.feature,
+                    &generation.model,
+                    generation.usage,
+                )
+                .await;
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MeteredLlm {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let result = self.inner.generate_text(prompt, params).await;
+        self.recorded(result).await
+    }
+
+    async fn generate_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<Generation, LlmError> {
+        let result = self.inner.generate_json(prompt, schema, params).await;
+        self.recorded(result).await
+    }
+
+    // Recorded once the whole reply has arrived
+    async fn stream_json(
+        &self,
+        prompt: &str,
+        schema: Option<&Value>,
+        params: &GenerationParams,
+    ) -> Result<GenerationStream, LlmError> {
+        let stream = self.inner.stream_json(prompt, schema, params).await?;
+        let meter = StreamMeter {
+            recorder: self.recorder.clone(),
+            scope: self.scope,
+            feature: self.feature,
+            model: stream.model.clone(),
+            prompt_tokens: estimate_tokens(prompt) as u32,
+            text: String::new(),
+            usage: None,
+            recorded: false,
+        };
+        let chunks = stream::unfold(
+            (stream.chunks, meter),
+            |(mut chunks, mut meter)| async move {
+                match chunks.next().await {
+                    Some(Ok(chunk)) => {
+                        meter.text.push_str(&chunk.text);
+                        meter.usage = chunk.usage.or(meter.usage);
+                        Some((Ok(chunk), (chunks, meter)))
+                    }
+                    Some(Err(e)) => {
+                        meter.record().await;
+                        Some((Err(e), (chunks, meter)))
+                    }
+                    None => {
+                        meter.record().await;
+                        None
+                    }
+                }
+            },
+        )
+        .boxed();
+        Ok(GenerationStream {
+            model: stream.model,
+            chunks,
+        })
+    }
+}
+
+// Records a streamed call once: when the stream ends or fails, or when it is
+// dropped before that (a consumer that stops early, a client that
+// disconnects). Without reported usage, the tokens are estimated from the
+// prompt and the text received so far.
+struct StreamMeter {
+    recorder: UsageRecorder,
+    scope: UsageScope,
+    feature: Feature,
+    model: String,
+    prompt_tokens: u32,
+    text: String,
+    usage: Option<TokenUsage>,
+    recorded: bool,
+}
+
+impl StreamMeter {
+    fn take_usage(&mut self) -> Option<TokenUsage> {
+        if std::mem::replace(&mut self.recorded, true) {
+            return None;
+        }
+        Some(self.usage.unwrap_or(TokenUsage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: estimate_tokens(&self.text) as u32,
+        }))
+    }
+
+    async fn record(&mut self) {
+        if let Some(usage) = self.take_usage() {
+            self.recorder
+                .record(self.scope, self.feature, &self.model, usage)
+                .await;
+        }
+    }
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        let Some(usage) = self.take_usage() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let (recorder, scope, feature) = (self.recorder.clone(), self.scope, self.feature);
+        let model = std::mem::take(&mut self.model);
+        runtime.spawn(async move { recorder.record(scope, feature, &model, usage).await });
+    }
+}
+
+struct MeteredEmbedder {
+    inner: Arc<dyn EmbeddingProvider>,
+    recorder: UsageRecorder,
+    scope: UsageScope,
+}
+
+#[async_trait]
+impl EmbeddingProvider for MeteredEmbedder {
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        task: EmbeddingTask,
+        dimensions: Option<usize>,
+    ) -> Result<Embeddings, LlmError> {
+        let result = self.inner.embed_batch(texts, task, dimensions).await;
+        if let Ok(embeddings) = &result {
+            self.recorder
+                .record(
+                    self.scope,
+                    Feature::Embedding,
+                    &embeddings.model,
+                    embeddings.usage,
+                )
+                .await;
+        }
+        result
+    }
+}
+
+// Why processing should pause, if this month's usage has reached a budget
+pub async fn budget_exceeded(
+    pool: &PgPool,
+    config: &UsageConfig,
+) -> Result<Option<String>, sqlx::Error> {
+    if config.monthly_budget_usd.is_none() && config.monthly_token_budget.is_none() {
+        return Ok(None);
+    }
+
+    let month = month_to_date(pool).await?;
+    if let Some(budget) = config.monthly_budget_usd {
+        if month.estimated_cost_usd >= budget {
+            return Ok(Some(format!(
+                "monthly budget of ${:.2} reached (${:.2} spent)",
+                budget, month.estimated_cost_usd
+            )));
+        }
+    }
+    if let Some(budget) = config.monthly_token_budget {
+        if month.tokens >= budget as i64 {
+            return Ok(Some(format!(
+                "monthly token budget of {} reached ({} used)",
+                budget, month.tokens
+            )));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Serialize, Debug)]
+pub struct MonthToDate {
+    pub tokens: i64,
+    pub estimated_cost_usd: f64,
+}
+
+pub async fn month_to_date(pool: &PgPool) -> Result<MonthToDate, sqlx::Error> {
+    sqlx::query_as!(
+        MonthToDate,
+        r#"
+        SELECT
+            COALESCE(SUM(prompt_tokens + completion_tokens + embedding_tokens), 0)::BIGINT AS "tokens!",
+            COALESCE(SUM(estimated_cost_usd), 0)::DOUBLE PRECISION AS "estimated_cost_usd!"
+        FROM llm_usage
+        WHERE created_at >= date_trunc('month', NOW())
+        "#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Dimension the admin usage report is grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGrouping {
+    Day,
+    Source,
+    Feature,
+}
+
+impl UsageGrouping {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "day" => Some(UsageGrouping::Day),
+            "source" => Some(UsageGrouping::Source),
+            "feature" => Some(UsageGrouping::Feature),
+            _ => None,
+        }
+    }
+
+    // Fixed SQL per variant; never built from user input
+    fn key_sql(&self) -> &'static str {
+        match self {
+            UsageGrouping::Day => "to_char(u.created_at, 'YYYY-MM-DD')",
+            // Calls not tied to a material (e.g. exam generation) have no source
+            UsageGrouping::Source => "COALESCE(m.source_type, 'none')",
+            UsageGrouping::Feature => "u.feature",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct UsageRow {
+    pub key: String,
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub embedding_tokens: i64,
+    pub estimated_cost_usd: f64,
+}
+
+// Usage between `from` and `to` (inclusive days), one row per group
+pub async fn summarize(
+    pool: &PgPool,
+    grouping: UsageGrouping,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<UsageRow>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT
+            {key} AS key,
+            COUNT(*) AS calls,
+            COALESCE(SUM(u.prompt_tokens), 0)::BIGINT AS prompt_tokens,
+            COALESCE(SUM(u.completion_tokens), 0)::BIGINT AS completion_tokens,
+            COALESCE(SUM(u.embedding_tokens), 0)::BIGINT AS embedding_tokens,
+            COALESCE(SUM(u.estimated_cost_usd), 0)::DOUBLE PRECISION AS estimated_cost_usd
+        FROM llm_usage u
+        LEFT JOIN raw_materials m ON m.id = u.raw_material_id
+        WHERE u.created_at >= $1::date AND u.created_at < $2::date + 1
+        GROUP BY 1
+        ORDER BY 1
+        "#,
+        key = grouping.key_sql()
+    );
+    sqlx::query_as::<_, UsageRow>(&sql)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_uses_longest_matching_price() {
+        let prices = UsageConfig::default().prices;
+        let usage = TokenUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 200_000,
+        };
+
+        let versioned = estimate_cost(&prices, "models/gemini-1.5-pro-002", usage);
+        assert!((versioned - 2.25).abs() < 1e-9, "{}", versioned);
+        assert_eq!(estimate_cost(&prices, "llama3.1", usage), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_summarized_and_budgeted() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        // A source type of its own keeps other tests' usage out of the group
+        let raw_id = Uuid::new_v4();
+        let source = format!("usage-test-{}", raw_id);
+        sqlx::query!(
+            "INSERT INTO raw_materials (id, url, content, source_type) VALUES ($1, $2, $3, $4)",
+            raw_id,
+            "http://test.com/usage-test",
+            "Usage Test Content",
+            source
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert raw material");
+
+        let config = UsageConfig {
+            monthly_budget_usd: Some(0.000001),
+            ..UsageConfig::default()
+        };
+        let recorder = UsageRecorder::new(pool.clone(), &config);
+        let scope = UsageScope {
+            raw_material_id: Some(raw_id),
+            ..UsageScope::default()
+        };
+        let usage = TokenUsage {
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+        };
+        recorder
+            .record(scope, Feature::Extraction, "gemini-1.5-pro", usage)
+            .await;
+        recorder
+            .record(scope, Feature::Embedding, "text-embedding-004", usage)
+            .await;
+
+        let today = chrono::Utc::now().date_naive();
+        let rows = summarize(&pool, UsageGrouping::Source, today, today)
+            .await
+            .expect("Failed to summarize usage");
+        let row = rows
+            .iter()
+            .find(|r| r.key == source)
+            .expect("no usage for the test source");
+        assert_eq!(row.calls, 2);
+        assert_eq!(row.prompt_tokens, 1000);
+        assert_eq!(row.completion_tokens, 1000);
+        assert_eq!(row.embedding_tokens, 1000);
+        assert!((row.estimated_cost_usd - 0.00375).abs() < 1e-9);
+
+        let exceeded = budget_exceeded(&pool, &config)
+            .await
+            .expect("Failed to check budget");
+        assert!(exceeded.is_some());
+
+        sqlx::query!("DELETE FROM llm_usage WHERE raw_material_id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM raw_materials WHERE id = $1", raw_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    // Streams two pieces without reporting usage
+    struct TwoPieces;
+
+    #[async_trait]
+    impl LlmProvider for TwoPieces {
+        async fn generate_text(
+            &self,
+            _prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!("only streaming is used")
+        }
+
+        async fn generate_json(
+            &self,
+            _prompt: &str,
+            _schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!("only streaming is used")
+        }
+
+        async fn stream_json(
+            &self,
+            _prompt: &str,
+            _schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<GenerationStream, LlmError> {
+            let pieces = [
+                "{\"questions\": [{\"stem\": \"First\"}",
+                ", {\"stem\": \"Second\"}]}",
+            ];
+            let chunks = stream::iter(pieces.map(|text| {
+                Ok(crate::core::traits::GenerationChunk {
+                    text: text.to_string(),
+                    usage: None,
+                })
+            }));
+            Ok(GenerationStream {
+                model: "test-stream".to_string(),
+                chunks: chunks.boxed(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_dropped_early_is_recorded() {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("Failed to connect to DB");
+
+        let recorder = UsageRecorder::new(pool.clone(), &UsageConfig::default());
+        let exam_id = Uuid::new_v4();
+        let scope = UsageScope {
+            exam_id: Some(exam_id),
+            ..UsageScope::default()
+        };
+        let llm = recorder.meter_llm(Arc::new(TwoPieces), scope, Feature::ExamGeneration);
+        let mut stream = llm
+            .stream_json("Write two questions", None, &GenerationParams::default())
+            .await
+            .unwrap();
+        let first = stream.chunks.next().await.unwrap().unwrap();
+        drop(stream);
+
+        // The dropped stream records in the background
+        let mut completion_tokens = None;
+        for _ in 0..50 {
+            completion_tokens = sqlx::query_scalar!(
+                "SELECT completion_tokens FROM llm_usage WHERE exam_id = $1",
+                exam_id
+            )
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+            if completion_tokens.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(
+            completion_tokens,
+            Some(estimate_tokens(&first.text) as i32),
+            "the received text should be estimated"
+        );
+
+        sqlx::query!("DELETE FROM llm_usage WHERE exam_id = $1", exam_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,403 @@
+use crate::core::config::GenerationParams;
+use crate::core::llm_error::LlmError;
+use crate::core::prompts::{self, PromptRegistry};
+use crate::core::questions::{AnswerKey, Question};
+use crate::core::structured::generate_structured;
+use crate::core::traits::LlmProvider;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// What the model says when it answers an item without seeing the key
+#[derive(Deserialize, JsonSchema, Debug)]
+struct AnswerCheck {
+    reasoning: String,
+    answer: String,
+    also_correct: Vec<String>,
+}
+
+// Outcome of checking an item's answer key
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStatus {
+    // Every sample chose the key and saw no other defensible choice
+    Verified,
+    // Most samples chose something other than the key
+    Disagreement,
+    // The samples split, or one saw more than one defensible choice
+    Ambiguous,
+    // The item has no key; `model_answer` is what the model would pick
+    MissingKey,
+}
+
+impl VerificationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VerificationStatus::Verified => "verified",
+            VerificationStatus::Disagreement => "disagreement",
+            VerificationStatus::Ambiguous => "ambiguous",
+            VerificationStatus::MissingKey => "missing_key",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub status: VerificationStatus,
+    // Share of samples that chose the key without naming another defensible
+    // choice: 1.0 is a unanimous, unambiguous match, 0.0 for a missing key
+    pub confidence: f64,
+    // Most common answer among the samples
+    pub model_answer: Option<AnswerKey>,
+    // Answer of each sample; None when it was not one of the choices
+    pub answers: Vec<Option<AnswerKey>>,
+    // Other choices any sample considered defensible
+    pub also_correct: Vec<AnswerKey>,
+    // The model's explanation for each sample, for reviewing flagged items
+    pub reasoning: Vec<String>,
+    pub prompt_version: String,
+}
+
+// One sample's answer, the other choices it would accept, and why
+struct Sample {
+    answer: Option<AnswerKey>,
+    also_correct: Vec<AnswerKey>,
+    reasoning: String,
+}
+
+// Re-answers items with the model, without their key, and compares
+pub struct Verifier {
+    llm: Arc<dyn LlmProvider>,
+    params: GenerationParams,
+    prompts: PromptRegistry,
+    samples: usize,
+}
+
+impl Verifier {
+    pub fn new(
+        llm: Arc<dyn LlmProvider>,
+        params: GenerationParams,
+        prompts: &PromptRegistry,
+        samples: usize,
+    ) -> Self {
+        Self {
+            llm,
+            params,
+            prompts: prompts.clone(),
+            samples: samples.max(1),
+        }
+    }
+
+    // `passage` is the text of the item's passage, if it has one. Each sample
+    // sees the choices in a different order (rotated by one per sample), so
+    // the requests differ and a preference for a position does not look like
+    // agreement. Error identification segments follow the sentence and cannot
+    // be reordered, so those items get a single sample. `key` chooses the
+    // prompt version when several are configured; a stored question passes
+    // its id, so a re-check uses the same version.
+    pub async fn verify(
+        &self,
+        question: &Question,
+        passage: Option<&str>,
+        key: u128,
+    ) -> Result<Verification, LlmError> {
+        let template =
+            self.prompts
+                .select(prompts::VERIFICATION, &self.params.prompt_versions, key);
+        let options = question.options();
+        let samples = match question {
+            Question::ErrorIdentification(_) => 1,
+            _ => self.samples.min(options.len()),
+        };
+
+        let mut results = Vec::with_capacity(samples);
+        for rotation in 0..samples {
+            let prompt = template.render(&[("question", &render(question, passage, rotation))]);
+            let check: AnswerCheck =
+                generate_structured(self.llm.as_ref(), &prompt, &self.params).await?;
+            // Letters name shown positions; map them back to the stored order
+            let original = |letter: &str| {
+                AnswerKey::parse(letter)
+                    .filter(|key| key.index() < options.len())
+                    .map(|key| AnswerKey::from_index((key.index() + rotation) % options.len()))
+            };
+            let answer = original(&check.answer);
+            let also_correct = check
+                .also_correct
+                .iter()
+                .filter_map(|letter| original(letter))
+                .filter(|key| Some(*key) != answer)
+                .collect();
+            results.push(Sample {
+                answer,
+                also_correct,
+                reasoning: check.reasoning,
+            });
+        }
+        Ok(assess(question.answer(), &results, template.id()))
+    }
+}
+
+// The item as shown to the model, choices rotated by `rotation`
+fn render(question: &Question, passage: Option<&str>, rotation: usize) -> String {
+    let options = question.options();
+    let mut text = String::new();
+    if let Some(passage) = passage {
+        text.push_str(&format!("PASSAGE:\n{}\n\n", passage));
+    }
+    let choices = match question {
+        Question::ErrorIdentification(_) => {
+            text.push_str(&format!(
+                "Which underlined part of this sentence contains an error?\n{}\n",
+                question.stem()
+            ));
+            "UNDERLINED PARTS"
+        }
+        _ => {
+            text.push_str(&format!(
+                "QUESTION ({}):\n{}\n",
+                question.question_type().as_str(),
+                question.stem()
+            ));
+            "CHOICES"
+        }
+    };
+    text.push_str(&format!("\n{}:\n", choices));
+    for i in 0..options.len() {
+        let option = &options[(i + rotation) % options.len()];
+        text.push_str(&format!("{}. {}\n", AnswerKey::from_index(i), option));
+    }
+    text
+}
+
+fn assess(key: Option<AnswerKey>, samples: &[Sample], prompt_version: String) -> Verification {
+    let answers: Vec<Option<AnswerKey>> = samples.iter().map(|s| s.answer).collect();
+    let mut votes: HashMap<AnswerKey, usize> = HashMap::new();
+    for answer in answers.iter().flatten() {
+        *votes.entry(*answer).or_default() += 1;
+    }
+    // Ties go to the earlier choice, so the result does not depend on
+    // hash order
+    let model_answer = votes
+        .iter()
+        .max_by_key(|(answer, count)| (**count, std::cmp::Reverse(answer.index())))
+        .map(|(answer, _)| *answer);
+    let mut also_correct: Vec<AnswerKey> = samples
+        .iter()
+        .flat_map(|s| s.also_correct.iter().copied())
+        .collect();
+    also_correct.sort_by_key(|key| key.index());
+    also_correct.dedup();
+
+    let reasoning = samples.iter().map(|s| s.reasoning.clone()).collect();
+
+    let (status, confidence) = match key {
+        None => (VerificationStatus::MissingKey, 0.0),
+        Some(key) => {
+            let agree = answers.iter().filter(|a| **a == Some(key)).count();
+            let clean = samples
+                .iter()
+                .filter(|s| s.answer == Some(key) && s.also_correct.is_empty())
+                .count();
+            let confidence = clean as f64 / samples.len().max(1) as f64;
+            let status = if agree * 2 < samples.len() {
+                VerificationStatus::Disagreement
+            } else if agree < samples.len() || !also_correct.is_empty() {
+                VerificationStatus::Ambiguous
+            } else {
+                VerificationStatus::Verified
+            };
+            (status, (confidence * 100.0).round() / 100.0)
+        }
+    };
+
+    Verification {
+        status,
+        confidence,
+        model_answer,
+        answers,
+        also_correct,
+        reasoning,
+        prompt_version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::traits::{Generation, TokenUsage};
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    // Always picks the choice whose text is `pick`, and records prompts
+    struct Picks {
+        pick: &'static str,
+        also: Option<&'static str>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    // Letter shown for the choice `text` in a rendered prompt
+    fn letter_of(prompt: &str, text: &str) -> String {
+        prompt
+            .lines()
+            .find_map(|line| {
+                let (letter, option) = line.split_once(". ")?;
+                (option == text && letter.len() == 1).then(|| letter.to_string())
+            })
+            .unwrap_or_default()
+    }
+
+    #[async_trait]
+    impl LlmProvider for Picks {
+        async fn generate_text(
+            &self,
+            _prompt: &str,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            unreachable!("only JSON is used")
+        }
+
+        async fn generate_json(
+            &self,
+            prompt: &str,
+            _schema: Option<&Value>,
+            _params: &GenerationParams,
+        ) -> Result<Generation, LlmError> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            let also: Vec<String> = self.also.iter().map(|t| letter_of(prompt, t)).collect();
+            let reply = json!({
+                "reasoning": "checked",
+                "answer": letter_of(prompt, self.pick),
+                "also_correct": also,
+            });
+            Ok(Generation {
+                text: reply.to_string(),
+                model: "test".to_string(),
+                usage: TokenUsage::default(),
+            })
+        }
+    }
+
+    fn question(answer: Option<&str>) -> Question {
+        serde_json::from_value(json!({
+            "type": "listening",
+            "stem": "Where is the speaker?",
+            "options": ["At home", "At work", "At school"],
+            "answer": answer,
+        }))
+        .unwrap()
+    }
+
+    async fn verify(
+        question: &Question,
+        pick: &'static str,
+        also: Option<&'static str>,
+    ) -> (Verification, Vec<String>) {
+        let llm = Arc::new(Picks {
+            pick,
+            also,
+            prompts: Mutex::new(Vec::new()),
+        });
+        let verifier = Verifier::new(
+            llm.clone(),
+            GenerationParams::default(),
+            &PromptRegistry::builtin(),
+            3,
+        );
+        let verification = verifier
+            .verify(question, Some("A: I'll see you at the office."), 0)
+            .await
+            .unwrap();
+        let prompts = llm.prompts.lock().unwrap().clone();
+        (verification, prompts)
+    }
+
+    #[tokio::test]
+    async fn test_answers_are_checked_against_the_key() {
+        // Each sample sees the choices in another order, yet all find B
+        let (verified, prompts) = verify(&question(Some("B")), "At work", None).await;
+        assert_eq!(prompts.len(), 3);
+        assert!(prompts[0].contains("A. At home") && prompts[1].contains("A. At work"));
+        assert!(prompts[0].contains("PASSAGE:\nA: I'll see you at the office."));
+        assert!(!prompts[0].contains("ANSWER"));
+        assert_eq!(verified.status, VerificationStatus::Verified);
+        assert_eq!(verified.confidence, 1.0);
+        assert_eq!(verified.prompt_version, "verification@v1");
+        assert_eq!(verified.reasoning, ["checked"; 3]);
+
+        let (wrong, _) = verify(&question(Some("A")), "At work", None).await;
+        assert_eq!(wrong.status, VerificationStatus::Disagreement);
+        assert_eq!(wrong.confidence, 0.0);
+        assert_eq!(wrong.model_answer, AnswerKey::parse("B"));
+
+        let (ambiguous, _) = verify(&question(Some("B")), "At work", Some("At school")).await;
+        assert_eq!(ambiguous.status, VerificationStatus::Ambiguous);
+        assert_eq!(ambiguous.confidence, 0.0);
+        assert_eq!(ambiguous.also_correct, [AnswerKey::from_index(2)]);
+
+        let (missing, _) = verify(&question(None), "At school", None).await;
+        assert_eq!(missing.status, VerificationStatus::MissingKey);
+        assert_eq!(missing.model_answer, AnswerKey::parse("C"));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_version_is_chosen_per_question() {
+        let dir = std::env::temp_dir().join(format!("prompts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let v2 = json!({
+            "name": "verification",
+            "version": 2,
+            "variables": ["question"],
+            "template": "Answer this item.\n{{question}}"
+        });
+        std::fs::write(dir.join("verification.v2.json"), v2.to_string()).unwrap();
+        let prompts = PromptRegistry::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let llm = Arc::new(Picks {
+            pick: "At work",
+            also: None,
+            prompts: Mutex::new(Vec::new()),
+        });
+        let params = GenerationParams {
+            prompt_versions: vec![1, 2],
+            ..GenerationParams::default()
+        };
+        let verifier = Verifier::new(llm, params, &prompts, 1);
+        let mut versions = Vec::new();
+        for key in [0, 1, 2] {
+            let verification = verifier
+                .verify(&question(Some("B")), None, key)
+                .await
+                .unwrap();
+            versions.push(verification.prompt_version);
+        }
+        assert_eq!(
+            versions,
+            ["verification@v1", "verification@v2", "verification@v1"]
+        );
+    }
+
+    #[test]
+    fn test_split_samples_lower_confidence() {
+        let sample = |answer: &str| Sample {
+            answer: AnswerKey::parse(answer),
+            also_correct: Vec::new(),
+            reasoning: String::new(),
+        };
+        let key = AnswerKey::parse("A");
+        let split = assess(key, &[sample("A"), sample("A"), sample("C")], String::new());
+        assert_eq!(split.status, VerificationStatus::Ambiguous);
+        assert_eq!(split.confidence, 0.67);
+        // A sample that gave no usable letter counts against the key
+        let unusable = assess(key, &[sample("A"), sample("?")], String::new());
+        assert_eq!(unusable.status, VerificationStatus::Ambiguous);
+        assert_eq!(unusable.answers, [key, None]);
+        let outvoted = assess(key, &[sample("B"), sample("A")], String::new());
+        assert_eq!(outvoted.status, VerificationStatus::Ambiguous);
+        let lost = assess(key, &[sample("B"), sample("B"), sample("A")], String::new());
+        assert_eq!(lost.status, VerificationStatus::Disagreement);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::core::config::Config;
+use crate::core::jobs::{self, Job};
+use crate::core::materials::{self, MaterialStatus};
+use crate::core::processor::{self, ProcessError};
+use crate::core::providers::Providers;
+use crate::core::usage;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// How often an idle worker checks the monthly budget, and how long one paused
+// by an exhausted budget waits before checking it again. It is an aggregate
+// over all of `llm_usage`, so it is not run on every poll.
+const BUDGET_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Start the background workers. Unfinished work from a previous run is picked
+// up automatically: expired leases are reclaimable and orphaned materials are
+// enqueued before the workers start polling.
+pub async fn spawn_workers(pool: PgPool, providers: Providers, config: Config) {
+    match jobs::enqueue_orphaned_materials(&pool, config.worker.max_attempts).await {
+        Ok(0) => {}
+        Ok(n) => println!("Enqueued {} unprocessed materials from a previous run", n),
+        Err(e) => eprintln!("Failed to enqueue unprocessed materials: {}", e),
+    }
+
+    for worker_id in 0..config.worker.concurrency.max(1) {
+        let pool = pool.clone();
+        let providers = providers.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            run_worker(worker_id, pool, providers, config).await;
+        });
+    }
+}
+
+async fn run_worker(worker_id: usize, pool: PgPool, providers: Providers, config: Config) {
+    let lease = Duration::from_secs(config.worker.lease_secs);
+    let poll_interval = Duration::from_millis(config.worker.poll_interval_ms);
+    let mut next_budget_check = Instant::now();
+
+    loop {
+        // Leave jobs queued while the provider is down instead of burning
+        // their attempts
+        if let Some(remaining) = providers.breaker.open_for() {
+            println!(
+                "Worker {}: provider unavailable, pausing for {}s",
+                worker_id,
+                remaining.as_secs().max(1)
+            );
+            tokio::time::sleep(remaining.max(poll_interval)).await;
+            continue;
+        }
+
+        // Same for a spent monthly budget; it is checked again periodically
+        // so processing resumes when the month rolls over or the limit is raised
+        if Instant::now() >= next_budget_check {
+            match usage::budget_exceeded(&pool, &config.usage).await {
+                Ok(Some(reason)) => {
+                    println!("Worker {}: {}, pausing processing", worker_id, reason);
+                    tokio::time::sleep(BUDGET_RECHECK_INTERVAL.max(poll_interval)).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Worker {}: failed to check usage budget: {}", worker_id, e),
+            }
+            next_budget_check = Instant::now() + BUDGET_RECHECK_INTERVAL;
+        }
+
+        let job = match jobs::claim(&pool, lease).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                match jobs::reap_expired(&pool).await {
+                    Ok(reaped) => {
+                        for job in reaped {
+                            let message = format!("Lease expired after {} attempts", job.attempts);
+                            mark_material(&pool, &job, MaterialStatus::Failed, &message).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Worker {}: failed to reap expired jobs: {}", worker_id, e),
+                }
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Worker {}: failed to claim job: {}", worker_id, e);
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        };
+
+        println!(
+            "Worker {}: running job {} (attempt {}/{})",
+            worker_id, job.id, job.attempts, job.max_attempts
+        );
+
+        let result = run_with_heartbeat(&pool, &providers, &config, &job, lease).await;
+        // The job may have spent what was left of the budget
+        next_budget_check = Instant::now();
+
+        let update = match result {
+            Ok(()) => jobs::complete(&pool, job.id).await,
+            Err(failure) => {
+                let e = failure.message;
+                if failure.retryable {
+                    eprintln!("Worker {}: job {} failed: {}", worker_id, job.id, e);
+                } else {
+                    eprintln!(
+                        "Worker {}: job {} failed permanently, not retrying: {}",
+                        worker_id, job.id, e
+                    );
+                }
+                match jobs::fail(&pool, &job, &e, failure.retryable).await {
+                    Ok(dead) => {
+                        let status = if dead {
+                            MaterialStatus::Failed
+                        } else {
+                            MaterialStatus::Queued
+                        };
+                        mark_material(&pool, &job, status, &e).await;
+                        Ok(())
+                    }
+                    Err(db_err) => Err(db_err),
+                }
+            }
+        };
+
+        if let Err(e) = update {
+            // The lease will expire and the job will be retried
+            eprintln!(
+                "Worker {}: failed to record result of job {}: {}",
+                worker_id, job.id, e
+            );
+        }
+    }
+}
+
+// A failed run of a job. Errors that cannot succeed on another attempt (e.g.
+// content blocked by the provider, a rejected API key) skip the remaining
+// retries and dead-letter the job at once.
+struct Failure {
+    message: String,
+    retryable: bool,
+}
+
+impl Failure {
+    fn retryable(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retryable: true,
+        }
+    }
+}
+
+impl From<ProcessError> for Failure {
+    fn from(e: ProcessError) -> Self {
+        // Keep what the provider actually said for permanent failures, which
+        // need a human to look at them
+        if let Some(body) = e
+            .llm_error()
+            .filter(|_| !e.is_retryable())
+            .and_then(|llm| llm.raw_body())
+        {
+            eprintln!(
+                "Provider response for permanent failure: {}",
+                body.chars().take(2000).collect::<String>()
+            );
+        }
+        Self {
+            message: e.to_string(),
+            retryable: e.is_retryable(),
+        }
+    }
+}
+
+// Reflect a failed attempt on the material: back to 'queued' while retries
+// remain, 'failed' once the job is dead-lettered
+async fn mark_material(pool: &PgPool, job: &Job, status: MaterialStatus, error: &str) {
+    if let Err(e) = materials::set_status(pool, job.raw_material_id, status, Some(error)).await {
+        eprintln!(
+            "Failed to update status of material {}: {}",
+            job.raw_material_id, e
+        );
+    }
+}
+
+// Run the job on its own task (so a panic is reported as a failure instead of
+// killing the worker) while periodically extending the lease.
+async fn run_with_heartbeat(
+    pool: &PgPool,
+    providers: &Providers,
+    config: &Config,
+    job: &Job,
+    lease: Duration,
+) -> Result<(), Failure> {
+    let handle = tokio::spawn(execute(
+        pool.clone(),
+        providers.clone(),
+        config.clone(),
+        job.clone(),
+    ));
+    tokio::pin!(handle);
+
+    let mut heartbeat = tokio::time::interval(lease / 3);
+    heartbeat.tick().await; // First tick completes immediately
+
+    loop {
+        tokio::select! {
+            joined = &mut handle => {
+                return match joined {
+                    Ok(result) => result,
+                    Err(e) if e.is_panic() => Err(Failure::retryable("Job panicked")),
+                    Err(e) => Err(Failure::retryable(e.to_string())),
+                };
+            }
+            _ = heartbeat.tick() => {
+                if let Err(e) = jobs::heartbeat(pool, job.id, lease).await {
+                    eprintln!("Failed to extend lease of job {}: {}", job.id, e);
+                }
+            }
+        }
+    }
+}
+
+async fn execute(
+    pool: PgPool,
+    providers: Providers,
+    config: Config,
+    job: Job,
+) -> Result<(), Failure> {
+    match job.kind.as_str() {
+        jobs::KIND_PROCESS_MATERIAL => {
+            // Prompts only ever see the cleaned text; rows ingested before
+            // normalization existed fall back to the raw content
+            let content = sqlx::query_scalar!(
+                r#"SELECT COALESCE(cleaned_content, content) AS "content!" FROM raw_materials WHERE id = $1"#,
+                job.raw_material_id
+            )
+            .fetch_one(&pool)
+            .await
+            .map_err(ProcessError::from)?;
+
+            processor::process_material(
+                job.raw_material_id,
+                Some(job.id),
+                content,
+                providers,
+                pool,
+                &config,
+            )
+            .await
+            .map_err(Failure::from)
+        }
+        other => Err(Failure {
+            message: format!("Unknown job kind: {}", other),
+            retryable: false,
+        }),
+    }
+}
@@ -1,9 +1,13 @@
+use crate::core::config::Config;
+use crate::core::providers::Providers;
 use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::env;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
+    pub config: Config,
+    pub providers: Providers,
 }
 
 pub async fn init_db() -> PgPool {
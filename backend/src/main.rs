@@ -1,6 +1,12 @@
+use crate::api::error::ApiError;
+use crate::core::cache;
 use crate::core::config::Config;
+use crate::core::providers::Providers;
+use crate::core::worker;
 use crate::db::init_db;
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
     Router,
 };
@@ -28,16 +34,53 @@ async fn main() {
         .await
         .expect("Failed to migrate database");
 
-    // 3. App State
-    let app_state = AppState { db: pool };
+    match cache::purge_expired(&pool).await {
+        Ok(0) => {}
+        Ok(n) => println!("Purged {} expired cache entries", n),
+        Err(e) => eprintln!("Failed to purge expired cache entries: {}", e),
+    }
 
-    // 4. Router
+    // 3. Background Workers (resume unfinished jobs from previous runs)
+    let providers = Providers::from_config(&config, pool.clone());
+    worker::spawn_workers(pool.clone(), providers.clone(), config.clone()).await;
+
+    // 4. App State
+    let max_body_bytes = config.max_body_bytes;
+    let app_state = AppState {
+        db: pool,
+        config,
+        providers,
+    };
+
+    // 5. Router
     let app = Router::new()
         .route("/health", get(|| async { "OK" }))
         .route("/internal/ingest", post(api::ingest::ingest_handler))
+        .route(
+            "/internal/ingest/batch",
+            post(api::ingest_batch::ingest_batch_handler),
+        )
+        .route("/internal/ingest/upload", post(api::upload::upload_handler))
+        .route(
+            "/internal/ingest/:id",
+            get(api::ingest::ingest_status_handler),
+        )
+        .route(
+            "/internal/materials",
+            get(api::materials::list_materials_handler),
+        )
+        .route(
+            "/internal/questions/similar",
+            get(api::questions::similar_questions_handler),
+        )
+        .route("/exams/stream", get(api::exams::stream_exam_handler))
+        .route("/admin/usage", get(api::usage::usage_handler))
+        .fallback(|| async { ApiError::not_found("Route not found") })
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::from_fn(api::error::request_context))
         .with_state(app_state);
 
-    // 5. Run Server
+    // 6. Run Server
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     println!("Listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();